@@ -0,0 +1,284 @@
+use std::ffi::OsStr;
+use std::path::PathBuf;
+use std::process::Command;
+use std::time::Instant;
+
+use crate::geometry::{Point, ScreenPoint, Size};
+use crate::image::{PixelFormat, Screenshot};
+use crate::Result;
+
+use super::{CaptureBackend, InputBackend, MouseButton};
+
+/// Runs `adb` commands against an Android device.
+#[derive(Clone, Debug)]
+pub struct Adb {
+    program: PathBuf,
+    serial: Option<String>,
+}
+
+impl Adb {
+    /// Uses the `adb` found on `PATH` and the only connected device.
+    pub fn new() -> Self {
+        Self::with_program("adb")
+    }
+
+    pub fn with_program<P: Into<PathBuf>>(program: P) -> Self {
+        Self {
+            program: program.into(),
+            serial: None,
+        }
+    }
+
+    /// Targets the device of this serial number, for when several are
+    /// connected.
+    pub fn set_serial(&mut self, serial: Option<String>) {
+        self.serial = serial;
+    }
+
+    /// Runs `adb` with `args`, returning its output.
+    pub fn run<I, S>(&self, args: I) -> Result<Vec<u8>>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        let mut command = Command::new(&self.program);
+        if let Some(serial) = &self.serial {
+            command.arg("-s").arg(serial);
+        }
+        command.args(args);
+        let output = command
+            .output()
+            .map_err(|err| format!("Failed to run {:?}: {}", command, err))?;
+        if !output.status.success() {
+            return Err(format!(
+                "{:?} failed with {}: {}",
+                command,
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        Ok(output.stdout)
+    }
+
+    fn shell_input(&self, args: &[String]) -> Result<()> {
+        let mut command = vec!["shell".to_string(), "input".to_string()];
+        command.extend_from_slice(args);
+        self.run(&command).map(|_| ())
+    }
+}
+
+impl Default for Adb {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Captures the screen of an Android device with `screencap`.
+pub struct AdbCapture {
+    adb: Adb,
+    size: Size,
+}
+
+impl AdbCapture {
+    /// Takes a first capture to learn the screen size.
+    pub fn new(adb: Adb) -> Result<Self> {
+        let mut capture = Self {
+            adb,
+            size: Size::default(),
+        };
+        capture.try_frame_into(&mut Screenshot::default())?;
+        Ok(capture)
+    }
+}
+
+impl CaptureBackend for AdbCapture {
+    fn screen_size(&self) -> Size {
+        self.size
+    }
+
+    fn try_frame_into(&mut self, screenshot: &mut Screenshot) -> Result<bool> {
+        let png = self.adb.run(["exec-out", "screencap", "-p"])?;
+        let image = image::load_from_memory(&png)
+            .map_err(|err| err.to_string())?
+            .into_rgba8();
+        let (width, height) = image.dimensions();
+        screenshot.fill_from_raw(width, height, width as usize * 4, PixelFormat::Rgba, &image)?;
+        self.size = screenshot.size();
+        Ok(true)
+    }
+}
+
+/// Simulates touches on an Android device with `input`.
+///
+/// The position of the mouse is only tracked, as there is no pointer on a
+/// touch screen. The left button taps, or swipes when dragged between
+/// `mouse_down` and `mouse_up`, the right button goes back and the middle one
+/// goes home. Scrolling swipes in the opposite direction.
+pub struct AdbInput {
+    adb: Adb,
+    position: Point,
+    pressed: Option<(Point, Instant)>,
+}
+
+impl AdbInput {
+    // Distance swiped by each step of scrolling
+    const SCROLL_STEP: i32 = 100;
+    // Duration of the swipes of scrolling and the least one of drags
+    const SWIPE_MILLIS: u128 = 300;
+
+    pub fn new(adb: Adb) -> Self {
+        Self {
+            adb,
+            position: Point::default(),
+            pressed: None,
+        }
+    }
+
+    fn tap(&self, pos: Point) -> Result<()> {
+        self.adb
+            .shell_input(&["tap".to_string(), pos.x.to_string(), pos.y.to_string()])
+    }
+
+    fn swipe(&self, from: Point, to: Point, millis: u128) -> Result<()> {
+        self.adb.shell_input(&[
+            "swipe".to_string(),
+            from.x.to_string(),
+            from.y.to_string(),
+            to.x.to_string(),
+            to.y.to_string(),
+            millis.to_string(),
+        ])
+    }
+
+    fn key(&self, btn: MouseButton) -> Result<()> {
+        let key = match btn {
+            MouseButton::Left => return Ok(()),
+            MouseButton::Middle => "KEYCODE_HOME",
+            MouseButton::Right => "KEYCODE_BACK",
+        };
+        self.adb
+            .shell_input(&["keyevent".to_string(), key.to_string()])
+    }
+}
+
+impl InputBackend for AdbInput {
+    fn cursor_position(&self) -> Result<ScreenPoint> {
+        Ok(self.position.into())
+    }
+
+    /// The touch screen has no negative positions, which are moved to its
+    /// edge.
+    fn mouse_move_to(&mut self, pos: ScreenPoint) -> Result<()> {
+        self.position = Point::new(pos.x.max(0) as u32, pos.y.max(0) as u32);
+        Ok(())
+    }
+
+    fn mouse_move_by(&mut self, dx: i32, dy: i32) -> Result<()> {
+        self.position = self.position.offset(dx, dy);
+        Ok(())
+    }
+
+    fn mouse_click(&mut self, btn: MouseButton) -> Result<()> {
+        match btn {
+            MouseButton::Left => self.tap(self.position),
+            _ => self.key(btn),
+        }
+    }
+
+    fn mouse_down(&mut self, btn: MouseButton) -> Result<()> {
+        if let MouseButton::Left = btn {
+            self.pressed = Some((self.position, Instant::now()));
+        }
+        Ok(())
+    }
+
+    fn mouse_up(&mut self, btn: MouseButton) -> Result<()> {
+        match btn {
+            MouseButton::Left => match self.pressed.take() {
+                Some((from, _)) if from == self.position => self.tap(from),
+                Some((from, time)) => {
+                    let millis = time.elapsed().as_millis().max(Self::SWIPE_MILLIS);
+                    self.swipe(from, self.position, millis)
+                }
+                None => Ok(()),
+            },
+            _ => self.key(btn),
+        }
+    }
+
+    fn mouse_scroll(&mut self, dx: i32, dy: i32) -> Result<()> {
+        if dx == 0 && dy == 0 {
+            return Ok(());
+        }
+        let to = self.position.offset(
+            -dx.saturating_mul(Self::SCROLL_STEP),
+            -dy.saturating_mul(Self::SCROLL_STEP),
+        );
+        self.swipe(self.position, to, Self::SWIPE_MILLIS)
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+
+    use image::{Rgba, RgbaImage};
+
+    use super::super::{Capturer, Simulator};
+    use super::*;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn drive_fake_device() {
+        let dir = TempDir::new("adb");
+        RgbaImage::from_pixel(3, 5, Rgba([50, 50, 50, 255]))
+            .save(dir.join("screen.png"))
+            .unwrap();
+
+        // Serves the screen and logs the other commands
+        let program = dir.join("adb");
+        fs::write(
+            &program,
+            "#!/bin/sh\n\
+             dir=$(dirname \"$0\")\n\
+             case \"$*\" in\n\
+             *'exec-out screencap -p') cat \"$dir/screen.png\" ;;\n\
+             *) echo \"$*\" >> \"$dir/log\" ;;\n\
+             esac\n",
+        )
+        .unwrap();
+        fs::set_permissions(&program, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let mut adb = Adb::with_program(&program);
+        adb.set_serial(Some("emulator-5554".to_string()));
+
+        let mut capturer = Capturer::with_backend(AdbCapture::new(adb.clone()).unwrap());
+        assert_eq!(capturer.screen_size(), Size::new(3, 5));
+        assert_eq!(capturer.frame().pixel(1, 1).luma(), 50);
+
+        let mut simulator = Simulator::with_backend(AdbInput::new(adb));
+        simulator.mouse_move_to(ScreenPoint::new(10, 20));
+        simulator.mouse_click(MouseButton::Left);
+        simulator.mouse_down(MouseButton::Left);
+        simulator.mouse_move_by(0, 300);
+        simulator.mouse_up(MouseButton::Left);
+        simulator.mouse_scroll(0, 2);
+        simulator.mouse_click(MouseButton::Right);
+
+        let log = fs::read_to_string(dir.join("log")).unwrap();
+        assert_eq!(
+            log.lines().collect::<Vec<_>>(),
+            [
+                "-s emulator-5554 shell input tap 10 20",
+                "-s emulator-5554 shell input swipe 10 20 10 320 300",
+                "-s emulator-5554 shell input swipe 10 320 10 120 300",
+                "-s emulator-5554 shell input keyevent KEYCODE_BACK",
+            ]
+        );
+
+        assert!(Adb::with_program(dir.join("missing"))
+            .run(["devices"])
+            .is_err());
+    }
+}
@@ -0,0 +1,256 @@
+use std::fs;
+use std::io::Cursor;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use image::codecs::gif::GifDecoder;
+use image::AnimationDecoder;
+
+use crate::geometry::Size;
+use crate::image::{PixelFormat, Screenshot};
+use crate::Result;
+
+use super::CaptureBackend;
+
+/// Feeds the frames of an animation to a `Capturer`, to reproduce timing
+/// issues offline.
+///
+/// By default one frame is returned per capture, or with `set_paced` the
+/// frame shown at the time elapsed since the first capture. The last frame
+/// is repeated once the animation is over, unless looping.
+pub struct AnimationCapture {
+    // Frames with the time they start to show
+    frames: Vec<(Duration, Screenshot)>,
+    duration: Duration,
+    paced: bool,
+    looping: bool,
+    start: Option<Instant>,
+    next: usize,
+}
+
+impl AnimationCapture {
+    pub fn from_gif_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::from_gif_buf(&fs::read(path).map_err(|err| err.to_string())?)
+    }
+
+    pub fn from_gif_buf(buf: &[u8]) -> Result<Self> {
+        let decoder = GifDecoder::new(Cursor::new(buf)).map_err(|err| err.to_string())?;
+        let mut frames = vec![];
+        for frame in decoder.into_frames() {
+            let frame = frame.map_err(|err| err.to_string())?;
+            let delay = Duration::from(frame.delay());
+            let buf = frame.into_buffer();
+            let screenshot = Screenshot::from_raw(
+                buf.width(),
+                buf.height(),
+                buf.width() as usize * 4,
+                PixelFormat::Rgba,
+                &buf,
+            )?;
+            frames.push((delay, screenshot));
+        }
+        Self::from_delayed_frames(frames)
+    }
+
+    /// Loads the images of a directory in the order of the numbers ending
+    /// their file names, such as `frame_1.png`, `frame_2.png`, ...,
+    /// `frame_10.png`, each showing for `interval`. Other files are ignored.
+    pub fn from_sequence_dir<P: AsRef<Path>>(dir: P, interval: Duration) -> Result<Self> {
+        let mut paths = vec![];
+        for entry in fs::read_dir(dir).map_err(|err| err.to_string())? {
+            let path = entry.map_err(|err| err.to_string())?.path();
+            if !path.is_file() || !Self::is_image(&path) {
+                continue;
+            }
+            let number = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .and_then(|stem| {
+                    let digits = stem
+                        .chars()
+                        .rev()
+                        .take_while(|c| c.is_ascii_digit())
+                        .count();
+                    stem[stem.len() - digits..].parse::<u64>().ok()
+                });
+            if let Some(number) = number {
+                paths.push((number, path));
+            }
+        }
+        paths.sort();
+
+        let mut frames = vec![];
+        for (_, path) in paths {
+            let buf = fs::read(&path).map_err(|err| err.to_string())?;
+            frames.push((interval, Screenshot::from_file_buf(&buf)?));
+        }
+        Self::from_delayed_frames(frames)
+    }
+
+    fn is_image(path: &Path) -> bool {
+        let extension = path
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase());
+        matches!(extension, Some(extension) if Self::EXTENSIONS.contains(&extension.as_str()))
+    }
+
+    const EXTENSIONS: [&'static str; 4] = ["png", "bmp", "jpg", "jpeg"];
+
+    fn from_delayed_frames(delayed_frames: Vec<(Duration, Screenshot)>) -> Result<Self> {
+        if delayed_frames.is_empty() {
+            return Err("Animation has no frames".to_string());
+        }
+
+        let mut frames = Vec::with_capacity(delayed_frames.len());
+        let mut time = Duration::default();
+        for (delay, screenshot) in delayed_frames {
+            frames.push((time, screenshot));
+            time += delay;
+        }
+
+        Ok(Self {
+            frames,
+            duration: time,
+            paced: false,
+            looping: false,
+            start: None,
+            next: 0,
+        })
+    }
+
+    /// Returns frames by the time elapsed since the first capture, instead of
+    /// one per capture.
+    pub fn set_paced(&mut self, paced: bool) {
+        self.paced = paced;
+    }
+
+    /// Starts over once the animation is over.
+    pub fn set_looping(&mut self, looping: bool) {
+        self.looping = looping;
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// Total time the frames show for.
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    pub fn frame(&self, index: usize) -> &Screenshot {
+        &self.frames[index].1
+    }
+
+    /// Returns the frame showing at `time` since the start of the animation.
+    pub fn frame_at(&self, time: Duration) -> &Screenshot {
+        let time = if self.looping && self.duration > Duration::default() {
+            Duration::from_nanos((time.as_nanos() % self.duration.as_nanos()) as u64)
+        } else {
+            time
+        };
+        let index = self
+            .frames
+            .iter()
+            .rposition(|(start, _)| *start <= time)
+            .unwrap_or(0);
+        &self.frames[index].1
+    }
+}
+
+impl CaptureBackend for AnimationCapture {
+    fn screen_size(&self) -> Size {
+        self.frames[0].1.size()
+    }
+
+    fn try_frame_into(&mut self, screenshot: &mut Screenshot) -> Result<bool> {
+        let frame = if self.paced {
+            let elapsed = self.start.get_or_insert_with(Instant::now).elapsed();
+            self.frame_at(elapsed)
+        } else {
+            let index = if self.looping {
+                self.next % self.frames.len()
+            } else {
+                self.next.min(self.frames.len() - 1)
+            };
+            self.next += 1;
+            &self.frames[index].1
+        };
+        screenshot.copy_from(frame);
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::codecs::gif::GifEncoder;
+    use image::{Delay, Frame, Rgba, RgbaImage};
+
+    use super::*;
+    use crate::test_util::TempDir;
+
+    fn gif(colors: &[u8], delay_ms: u32) -> Vec<u8> {
+        let mut buf = vec![];
+        {
+            let mut encoder = GifEncoder::new(&mut buf);
+            for &color in colors {
+                let image = RgbaImage::from_pixel(4, 3, Rgba([color, color, color, 255]));
+                let delay = Delay::from_numer_denom_ms(delay_ms, 1);
+                encoder
+                    .encode_frame(Frame::from_parts(image, 0, 0, delay))
+                    .unwrap();
+            }
+        }
+        buf
+    }
+
+    fn luma(screenshot: &Screenshot) -> u8 {
+        screenshot.pixel(0, 0).luma()
+    }
+
+    #[test]
+    fn frames_of_gif() {
+        let mut animation = AnimationCapture::from_gif_buf(&gif(&[0, 255], 100)).unwrap();
+        assert_eq!(animation.len(), 2);
+        assert_eq!(animation.screen_size(), Size::new(4, 3));
+        assert_eq!(animation.duration(), Duration::from_millis(200));
+        assert_eq!(luma(animation.frame_at(Duration::from_millis(50))), 0);
+        assert_eq!(luma(animation.frame_at(Duration::from_millis(150))), 255);
+        assert_eq!(luma(animation.frame_at(Duration::from_millis(250))), 255);
+
+        animation.set_looping(true);
+        assert_eq!(luma(animation.frame_at(Duration::from_millis(250))), 0);
+
+        let mut screenshot = Screenshot::default();
+        let lumas: Vec<_> = (0..3)
+            .map(|_| {
+                animation.try_frame_into(&mut screenshot).unwrap();
+                luma(&screenshot)
+            })
+            .collect();
+        assert_eq!(lumas, vec![0, 255, 0]);
+    }
+
+    #[test]
+    fn frames_of_sequence() {
+        let dir = TempDir::new("sequence");
+        for (number, color) in [(2, 20u8), (10, 100), (1, 10)] {
+            let image = RgbaImage::from_pixel(2, 2, Rgba([color, color, color, 255]));
+            image
+                .save(dir.join(format!("take2_frame{}.png", number)))
+                .unwrap();
+        }
+        fs::write(dir.join("notes2.txt"), "not a frame").unwrap();
+
+        let animation =
+            AnimationCapture::from_sequence_dir(&dir, Duration::from_millis(10)).unwrap();
+        let lumas: Vec<_> = (0..animation.len())
+            .map(|i| luma(animation.frame(i)))
+            .collect();
+        assert_eq!(lumas, vec![10, 20, 100]);
+    }
+}
@@ -1,46 +1,141 @@
-use std::io::ErrorKind::WouldBlock;
+use std::mem;
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
+use crate::geometry::{Rect, Size};
 use crate::image::Screenshot;
+use crate::Result;
+
+#[cfg(not(target_os = "linux"))]
+use super::DesktopCapture;
+use super::Recorder;
+#[cfg(target_os = "linux")]
+use super::X11Capture;
+
+/// Source of the frames returned by a `Capturer`.
+pub trait CaptureBackend {
+    fn screen_size(&self) -> Size;
+
+    /// Captures a frame into `screenshot`, returning false if no frame is
+    /// ready yet.
+    fn try_frame_into(&mut self, screenshot: &mut Screenshot) -> Result<bool>;
+}
 
 pub struct Capturer {
-    capturer: scrap::Capturer,
+    backend: Box<dyn CaptureBackend>,
+    recorder: Option<Recorder>,
 }
 
 impl Capturer {
+    /// Captures the primary display, through X11 on Linux.
     pub fn new() -> Self {
-        let display = scrap::Display::primary().expect("Failed to find primary display.");
-        let capturer = scrap::Capturer::new(display).expect("Failed to begin capture.");
-        Self { capturer }
+        #[cfg(target_os = "linux")]
+        let backend = X11Capture::connect(None).expect("Failed to connect to X server.");
+        #[cfg(not(target_os = "linux"))]
+        let backend = DesktopCapture::new();
+        Self::with_backend(backend)
+    }
+
+    pub fn with_backend<B: CaptureBackend + 'static>(backend: B) -> Self {
+        Self {
+            backend: Box::new(backend),
+            recorder: None,
+        }
+    }
+
+    pub fn screen_size(&self) -> Size {
+        self.backend.screen_size()
     }
 
-    pub fn screen_size(&self) -> (u32, u32) {
-        (self.capturer.width() as u32, self.capturer.height() as u32)
+    /// Records every captured frame, or stops recording if `None`.
+    pub fn set_recorder(&mut self, recorder: Option<Recorder>) {
+        self.recorder = recorder;
     }
 
     pub fn frame(&mut self) -> Screenshot {
+        let mut screenshot = Screenshot::default();
+        self.frame_into(&mut screenshot);
+        screenshot
+    }
+
+    /// Captures a frame into `screenshot`, reusing its buffer.
+    pub fn frame_into(&mut self, screenshot: &mut Screenshot) {
         let one_second = Duration::new(1, 0);
         let one_frame = one_second / 60;
 
-        let (w, h) = (self.capturer.width(), self.capturer.height());
+        // Wait until there's a frame.
+        while !self
+            .backend
+            .try_frame_into(screenshot)
+            .unwrap_or_else(|error| panic!("{}", error))
+        {
+            // Keep spinning.
+            thread::sleep(one_frame);
+        }
+
+        if let Some(recorder) = &self.recorder {
+            recorder.record_frame(screenshot);
+        }
+    }
+}
 
+impl Capturer {
+    /// Waits until the screen, or `region` of it, has not changed for
+    /// `duration`, returning the stable frame. Polls every `interval` and
+    /// gives up after `timeout`.
+    pub fn wait_until_stable(
+        &mut self,
+        region: Option<Rect>,
+        duration: Duration,
+        interval: Duration,
+        timeout: Duration,
+    ) -> Option<Screenshot> {
+        let start = Instant::now();
+        let mut frames = FrameBuffer::new();
+        frames.capture(self);
+        let mut stable_since = Instant::now();
         loop {
-            // Wait until there's a frame.
-            let frame = match self.capturer.frame() {
-                Ok(frame) => frame,
-                Err(error) => {
-                    if error.kind() == WouldBlock {
-                        // Keep spinning.
-                        thread::sleep(one_frame);
-                        continue;
-                    } else {
-                        panic!("Error: {}", error);
-                    }
-                }
-            };
-
-            return Screenshot::from_bgra_buf(w as u32, h as u32, frame.to_vec()).unwrap();
+            let poll = Instant::now();
+            frames.capture(self);
+            let now = Instant::now();
+            if !frames.curr().diff(frames.prev(), region).is_empty() {
+                stable_since = now;
+            } else if now - stable_since >= duration {
+                return Some(mem::take(&mut frames.curr));
+            }
+            if now - start >= timeout {
+                return None;
+            }
+            if let Some(rest) = interval.checked_sub(poll.elapsed()) {
+                thread::sleep(rest);
+            }
+        }
+    }
+
+    /// Waits until the screen, or `region` of it, differs from `reference`,
+    /// returning the changed frame. Polls every `interval` and gives up after
+    /// `timeout`.
+    pub fn wait_until_changed(
+        &mut self,
+        reference: &Screenshot,
+        region: Option<Rect>,
+        interval: Duration,
+        timeout: Duration,
+    ) -> Option<Screenshot> {
+        let start = Instant::now();
+        let mut screenshot = Screenshot::default();
+        loop {
+            let poll = Instant::now();
+            self.frame_into(&mut screenshot);
+            if !screenshot.diff(reference, region).is_empty() {
+                return Some(screenshot);
+            }
+            if start.elapsed() >= timeout {
+                return None;
+            }
+            if let Some(rest) = interval.checked_sub(poll.elapsed()) {
+                thread::sleep(rest);
+            }
         }
     }
 }
@@ -50,3 +145,32 @@ impl Default for Capturer {
         Self::new()
     }
 }
+
+/// Two screenshots captured in turn, so that the previous frame stays
+/// available and no buffer is allocated once both are filled.
+#[derive(Default)]
+pub struct FrameBuffer {
+    curr: Screenshot,
+    prev: Screenshot,
+}
+
+impl FrameBuffer {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Captures a frame into the older buffer and returns it.
+    pub fn capture(&mut self, capturer: &mut Capturer) -> &Screenshot {
+        mem::swap(&mut self.curr, &mut self.prev);
+        capturer.frame_into(&mut self.curr);
+        &self.curr
+    }
+
+    pub fn curr(&self) -> &Screenshot {
+        &self.curr
+    }
+
+    pub fn prev(&self) -> &Screenshot {
+        &self.prev
+    }
+}
@@ -0,0 +1,205 @@
+use crate::geometry::{Point, ScreenPoint};
+
+/// Spaces that positions on the screen are expressed in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CoordinateSpace {
+    /// Pixels of the captured frames, as found by a `Finder`. Frames may
+    /// cover a region of the screen only, or be scaled.
+    Capture,
+    /// Pixels of the screen.
+    Physical,
+    /// Pixels of the screen divided by the display scale factor, as used by
+    /// applications unaware of display scaling.
+    Logical,
+}
+
+/// Converts positions between the coordinate spaces of a screen.
+///
+/// By default all spaces coincide, which holds for a capture of the whole
+/// screen without display scaling.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CoordinateMapping {
+    capture_origin: (i32, i32),
+    capture_scale: f64,
+    display_scale: f64,
+    input_space: CoordinateSpace,
+}
+
+impl CoordinateMapping {
+    pub fn new() -> Self {
+        Self {
+            capture_origin: (0, 0),
+            capture_scale: 1.0,
+            display_scale: 1.0,
+            input_space: CoordinateSpace::Physical,
+        }
+    }
+
+    /// Physical position of the top left corner of the captured frames.
+    pub fn capture_origin(&self) -> (i32, i32) {
+        self.capture_origin
+    }
+
+    pub fn set_capture_origin(&mut self, x: i32, y: i32) {
+        self.capture_origin = (x, y);
+    }
+
+    /// Physical pixels per captured pixel.
+    pub fn capture_scale(&self) -> f64 {
+        self.capture_scale
+    }
+
+    /// # Panics
+    ///
+    /// If `scale` isn't finite and positive.
+    pub fn set_capture_scale(&mut self, scale: f64) {
+        assert!(
+            scale.is_finite() && scale > 0.0,
+            "Invalid capture scale {}",
+            scale
+        );
+        self.capture_scale = scale;
+    }
+
+    /// Physical pixels per logical pixel, such as 1.5 for a display scaled
+    /// to 150%.
+    pub fn display_scale(&self) -> f64 {
+        self.display_scale
+    }
+
+    /// # Panics
+    ///
+    /// If `scale` isn't finite and positive.
+    pub fn set_display_scale(&mut self, scale: f64) {
+        assert!(
+            scale.is_finite() && scale > 0.0,
+            "Invalid display scale {}",
+            scale
+        );
+        self.display_scale = scale;
+    }
+
+    /// Space of the positions taken by the `Simulator`.
+    pub fn input_space(&self) -> CoordinateSpace {
+        self.input_space
+    }
+
+    pub fn set_input_space(&mut self, space: CoordinateSpace) {
+        self.input_space = space;
+    }
+
+    fn space_to_physical(&self, (x, y): (f64, f64), from: CoordinateSpace) -> (f64, f64) {
+        match from {
+            CoordinateSpace::Capture => (
+                self.capture_origin.0 as f64 + x * self.capture_scale,
+                self.capture_origin.1 as f64 + y * self.capture_scale,
+            ),
+            CoordinateSpace::Physical => (x, y),
+            CoordinateSpace::Logical => (x * self.display_scale, y * self.display_scale),
+        }
+    }
+
+    fn physical_to_space(&self, (x, y): (f64, f64), to: CoordinateSpace) -> (f64, f64) {
+        match to {
+            CoordinateSpace::Capture => (
+                (x - self.capture_origin.0 as f64) / self.capture_scale,
+                (y - self.capture_origin.1 as f64) / self.capture_scale,
+            ),
+            CoordinateSpace::Physical => (x, y),
+            CoordinateSpace::Logical => (x / self.display_scale, y / self.display_scale),
+        }
+    }
+
+    /// Converts a position between two spaces, rounded to the nearest pixel.
+    pub fn convert(
+        &self,
+        pos: ScreenPoint,
+        from: CoordinateSpace,
+        to: CoordinateSpace,
+    ) -> ScreenPoint {
+        let physical = self.space_to_physical((pos.x as f64, pos.y as f64), from);
+        let (x, y) = self.physical_to_space(physical, to);
+        ScreenPoint::new(x.round() as i32, y.round() as i32)
+    }
+
+    /// Converts a position in captured frames to one for the `Simulator`.
+    pub fn capture_to_input(&self, pos: Point) -> ScreenPoint {
+        self.convert(pos.into(), CoordinateSpace::Capture, self.input_space)
+    }
+
+    /// Converts a position of the `Simulator` to one in captured frames, if
+    /// not left of or above them.
+    pub fn input_to_capture(&self, pos: ScreenPoint) -> Option<Point> {
+        let pos = self.convert(pos, self.input_space, CoordinateSpace::Capture);
+        if pos.x < 0 || pos.y < 0 {
+            return None;
+        }
+        Some(Point::new(pos.x as u32, pos.y as u32))
+    }
+}
+
+impl Default for CoordinateMapping {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn convert_between_spaces() {
+        let mut mapping = CoordinateMapping::new();
+        assert_eq!(
+            mapping.capture_to_input(Point::new(10, 20)),
+            ScreenPoint::new(10, 20)
+        );
+
+        // A half-sized capture of the right screen of two, scaled to 200%
+        mapping.set_capture_origin(1920, 0);
+        mapping.set_capture_scale(2.0);
+        mapping.set_display_scale(2.0);
+        assert_eq!(
+            mapping.capture_to_input(Point::new(10, 20)),
+            ScreenPoint::new(1940, 40)
+        );
+
+        mapping.set_input_space(CoordinateSpace::Logical);
+        assert_eq!(
+            mapping.capture_to_input(Point::new(10, 20)),
+            ScreenPoint::new(970, 20)
+        );
+        assert_eq!(
+            mapping.input_to_capture(ScreenPoint::new(970, 20)),
+            Some(Point::new(10, 20))
+        );
+        assert_eq!(
+            mapping.convert(
+                ScreenPoint::new(0, 0),
+                CoordinateSpace::Physical,
+                CoordinateSpace::Capture
+            ),
+            ScreenPoint::new(-960, 0)
+        );
+
+        // A capture of a screen left of the primary one
+        let mut mapping = CoordinateMapping::new();
+        mapping.set_capture_origin(-1920, 0);
+        assert_eq!(
+            mapping.capture_to_input(Point::new(10, 20)),
+            ScreenPoint::new(-1910, 20)
+        );
+        assert_eq!(
+            mapping.input_to_capture(ScreenPoint::new(-1910, 20)),
+            Some(Point::new(10, 20))
+        );
+        assert_eq!(mapping.input_to_capture(ScreenPoint::new(-2000, 20)), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid display scale NaN")]
+    fn reject_invalid_scale() {
+        CoordinateMapping::new().set_display_scale(f64::NAN);
+    }
+}
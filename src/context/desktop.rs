@@ -0,0 +1,121 @@
+use std::io::ErrorKind::WouldBlock;
+
+use tfc::{MouseContext, ScreenContext};
+
+use crate::geometry::{ScreenPoint, Size};
+use crate::image::{PixelFormat, Screenshot};
+use crate::Result;
+
+use super::{CaptureBackend, InputBackend, MouseButton};
+
+/// Captures the primary display of the desktop.
+pub struct DesktopCapture {
+    capturer: scrap::Capturer,
+}
+
+impl DesktopCapture {
+    pub fn new() -> Self {
+        let display = scrap::Display::primary().expect("Failed to find primary display.");
+        let capturer = scrap::Capturer::new(display).expect("Failed to begin capture.");
+        Self { capturer }
+    }
+}
+
+impl Default for DesktopCapture {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CaptureBackend for DesktopCapture {
+    fn screen_size(&self) -> Size {
+        Size::new(self.capturer.width() as u32, self.capturer.height() as u32)
+    }
+
+    fn try_frame_into(&mut self, screenshot: &mut Screenshot) -> Result<bool> {
+        let (w, h) = (self.capturer.width(), self.capturer.height());
+        let frame = match self.capturer.frame() {
+            Ok(frame) => frame,
+            Err(error) if error.kind() == WouldBlock => return Ok(false),
+            Err(error) => return Err(format!("Error: {}", error)),
+        };
+
+        // Rows may be padded to an alignment, so the stride is derived from
+        // the frame rather than the width
+        let stride = frame.len() / h;
+        screenshot.fill_from_raw(w as u32, h as u32, stride, PixelFormat::Bgra, &frame)?;
+        Ok(true)
+    }
+}
+
+/// Simulates input on the desktop.
+pub struct DesktopInput {
+    context: tfc::Context,
+}
+
+impl DesktopInput {
+    pub fn new() -> Self {
+        let context = tfc::Context::new().expect("Failed to get context");
+        Self { context }
+    }
+}
+
+impl Default for DesktopInput {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn tfc_button(btn: MouseButton) -> tfc::MouseButton {
+    match btn {
+        MouseButton::Left => tfc::MouseButton::Left,
+        MouseButton::Middle => tfc::MouseButton::Middle,
+        MouseButton::Right => tfc::MouseButton::Right,
+    }
+}
+
+impl InputBackend for DesktopInput {
+    fn cursor_position(&self) -> Result<ScreenPoint> {
+        let (x, y) = self
+            .context
+            .cursor_location()
+            .map_err(|err| format!("{:?}", err))?;
+        Ok(ScreenPoint::new(x, y))
+    }
+
+    fn mouse_move_to(&mut self, pos: ScreenPoint) -> Result<()> {
+        self.context
+            .mouse_move_abs(pos.x, pos.y)
+            .map_err(|err| format!("{:?}", err))
+    }
+
+    fn mouse_move_by(&mut self, dx: i32, dy: i32) -> Result<()> {
+        self.context
+            .mouse_move_rel(dx, dy)
+            .map_err(|err| format!("{:?}", err))
+    }
+
+    fn mouse_click(&mut self, btn: MouseButton) -> Result<()> {
+        self.context
+            .mouse_click(tfc_button(btn))
+            .map_err(|err| format!("{:?}", err))
+    }
+
+    fn mouse_down(&mut self, btn: MouseButton) -> Result<()> {
+        self.context
+            .mouse_down(tfc_button(btn))
+            .map_err(|err| format!("{:?}", err))
+    }
+
+    fn mouse_up(&mut self, btn: MouseButton) -> Result<()> {
+        self.context
+            .mouse_up(tfc_button(btn))
+            .map_err(|err| format!("{:?}", err))
+    }
+
+    fn mouse_scroll(&mut self, dx: i32, dy: i32) -> Result<()> {
+        self.context
+            .mouse_scroll(dx, dy)
+            .map_err(|err| format!("{:?}", err))
+    }
+}
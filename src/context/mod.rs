@@ -1,19 +1,103 @@
+mod adb;
+mod animation;
 mod capturer;
+mod coordinates;
+#[cfg(not(target_os = "linux"))]
+mod desktop;
+mod profile;
+mod recorder;
+mod replay;
 mod simulator;
+mod vnc;
+mod watch;
+mod worker;
+#[cfg(target_os = "linux")]
+mod x11;
 
+pub use adb::*;
+pub use animation::*;
 pub use capturer::*;
+pub use coordinates::*;
+#[cfg(not(target_os = "linux"))]
+pub use desktop::*;
+pub use profile::*;
+pub use recorder::*;
+pub use replay::*;
 pub use simulator::*;
+pub use vnc::*;
+pub use watch::*;
+pub use worker::*;
+#[cfg(target_os = "linux")]
+pub use x11::*;
+
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::fsm::StateId;
+use crate::geometry::{Point, Rect, ScreenPoint};
+use crate::image::{LoadHook, Pattern, PatternLibrary, Reload, Screenshot};
+use crate::Result;
+
+/// Where `Context::frame` keeps the cursor while capturing, so that it does
+/// not hide what is under it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CursorPolicy {
+    /// Leaves the cursor where it is.
+    #[default]
+    Keep,
+    /// Moves the cursor to this position, in the input space, before
+    /// capturing.
+    Park(ScreenPoint),
+    /// Moves the cursor to this position before capturing and back
+    /// afterwards.
+    ParkAndRestore(ScreenPoint),
+}
+
+/// Called with the outcome of every reload of the pattern library of a
+/// `Context` that changed something.
+pub type ReloadHook = Box<dyn FnMut(&Reload)>;
 
 pub struct Context {
     capturer: Capturer,
     simulator: Simulator,
+    screenshot: Screenshot,
+    worker: Option<CaptureWorker>,
+    latest: Option<Arc<Screenshot>>,
+    recorder: Option<Recorder>,
+    cursor_policy: CursorPolicy,
+    coordinate_mapping: CoordinateMapping,
+    resolution_profile: Option<ResolutionProfile>,
+    library: Option<PatternLibrary>,
+    hot_reload: Option<Duration>,
+    reload_time: Option<Instant>,
+    last_reload: Option<Reload>,
+    on_reload: Option<ReloadHook>,
 }
 
 impl Context {
     pub fn new() -> Context {
+        Self::from_parts(Default::default(), Default::default())
+    }
+
+    /// Creates a context capturing and simulating input through other
+    /// backends than the desktop.
+    pub fn from_parts(capturer: Capturer, simulator: Simulator) -> Context {
         Context {
-            capturer: Default::default(),
-            simulator: Default::default(),
+            capturer,
+            simulator,
+            screenshot: Default::default(),
+            worker: None,
+            latest: None,
+            recorder: None,
+            cursor_policy: Default::default(),
+            coordinate_mapping: Default::default(),
+            resolution_profile: None,
+            library: None,
+            hot_reload: None,
+            reload_time: None,
+            last_reload: None,
+            on_reload: None,
         }
     }
     pub fn capturer_mut(&mut self) -> &mut Capturer {
@@ -22,6 +106,275 @@ impl Context {
     pub fn simulator_mut(&mut self) -> &mut Simulator {
         &mut self.simulator
     }
+
+    pub fn worker(&self) -> Option<&CaptureWorker> {
+        self.worker.as_ref()
+    }
+
+    /// Lets frames be taken from a background worker instead of capturing
+    /// them on demand.
+    pub fn set_worker(&mut self, worker: Option<CaptureWorker>) {
+        self.worker = worker;
+        self.latest = None;
+    }
+
+    pub fn cursor_policy(&self) -> CursorPolicy {
+        self.cursor_policy
+    }
+
+    pub fn set_cursor_policy(&mut self, cursor_policy: CursorPolicy) {
+        self.cursor_policy = cursor_policy;
+    }
+
+    pub fn coordinate_mapping(&self) -> &CoordinateMapping {
+        &self.coordinate_mapping
+    }
+
+    /// Sets how positions in captured frames relate to the positions taken
+    /// by the simulator, see `CoordinateMapping`.
+    pub fn set_coordinate_mapping(&mut self, coordinate_mapping: CoordinateMapping) {
+        self.coordinate_mapping = coordinate_mapping;
+    }
+
+    /// Converts a position in captured frames, such as one found by a
+    /// `Finder`, to one for the simulator.
+    pub fn capture_to_input(&self, pos: Point) -> ScreenPoint {
+        self.coordinate_mapping.capture_to_input(pos)
+    }
+
+    /// Moves the mouse to a position in captured frames.
+    pub fn mouse_move_to_capture(&mut self, pos: Point) {
+        let pos = self.capture_to_input(pos);
+        self.simulator.mouse_move_to(pos);
+    }
+
+    pub fn resolution_profile(&self) -> Option<&ResolutionProfile> {
+        self.resolution_profile.as_ref()
+    }
+
+    /// Declares the resolution the script was authored at, so that patterns
+    /// loaded by `load_pattern` and positions mapped by `map_point` and
+    /// `map_rect` fit the screen of the capturer.
+    pub fn set_resolution_profile(&mut self, resolution_profile: Option<ResolutionProfile>) {
+        self.resolution_profile = resolution_profile;
+    }
+
+    /// Loads a pattern cropped at the reference resolution, see
+    /// `Pattern::from_file_buf`.
+    pub fn load_pattern(&self, buf: &[u8]) -> Result<Pattern> {
+        match &self.resolution_profile {
+            Some(profile) => Pattern::from_file_buf_scaled(
+                buf,
+                profile.pattern_scale_to(self.capturer.screen_size()),
+            ),
+            None => Pattern::from_file_buf(buf),
+        }
+    }
+
+    /// Loads a library of patterns cropped at the reference resolution, with
+    /// their regions at that resolution too, see `PatternLibrary`.
+    pub fn load_library<P: AsRef<Path>>(&self, dir: P) -> Result<PatternLibrary> {
+        let profile = match &self.resolution_profile {
+            Some(profile) => *profile,
+            None => return PatternLibrary::load(dir),
+        };
+        let size = self.capturer.screen_size();
+        let scale = profile.pattern_scale_to(size);
+        let on_load: LoadHook = Box::new(move |pattern: &mut Pattern| {
+            let region = pattern
+                .region()
+                .map(|region| profile.map_rect(region, size));
+            pattern.set_region(region);
+        });
+        PatternLibrary::load_with(dir, scale, Some(on_load))
+    }
+
+    pub fn pattern_library(&self) -> Option<&PatternLibrary> {
+        self.library.as_ref()
+    }
+
+    /// Sets the library the patterns named by the preset states are taken
+    /// from, see `pattern`.
+    pub fn set_pattern_library(&mut self, library: Option<PatternLibrary>) {
+        self.library = library;
+        self.reload_time = Some(Instant::now());
+    }
+
+    /// Returns a pattern of the library, which stays valid when the library
+    /// is reloaded.
+    pub fn pattern(&self, name: &str) -> Result<Arc<Pattern>> {
+        let library = self
+            .library
+            .as_ref()
+            .ok_or_else(|| "No pattern library".to_string())?;
+        library
+            .shared(name)
+            .ok_or_else(|| format!("No pattern named `{}`", name))
+    }
+
+    /// Lets `frame` reload the patterns of the library changed on disk, at
+    /// most once per `interval`, which is meant for authoring automations.
+    pub fn set_hot_reload(&mut self, interval: Option<Duration>) {
+        self.hot_reload = interval;
+    }
+
+    /// Sets the hook told about the reloads that changed something, such as
+    /// to report the patterns that failed to load.
+    pub fn set_on_reload(&mut self, hook: Option<ReloadHook>) {
+        self.on_reload = hook;
+    }
+
+    /// Reloads the patterns of the library changed on disk, see
+    /// `PatternLibrary::reload_changed`.
+    pub fn reload_patterns(&mut self) -> Reload {
+        self.reload_time = Some(Instant::now());
+        let reload = match &mut self.library {
+            Some(library) => library.reload_changed(),
+            None => return Reload::default(),
+        };
+        if reload.is_empty() {
+            return reload;
+        }
+        if let Some(hook) = &mut self.on_reload {
+            hook(&reload);
+        }
+        self.last_reload = Some(reload.clone());
+        reload
+    }
+
+    /// Reloads the patterns if hot reload is on and its interval has passed,
+    /// as `frame` does. Taking patterns from the library after polling keeps
+    /// them from being older than the frame they are matched against.
+    pub fn poll_hot_reload(&mut self) {
+        if let Some(interval) = self.hot_reload {
+            let due = match self.reload_time {
+                Some(last) => last.elapsed() >= interval,
+                None => true,
+            };
+            if due {
+                self.reload_patterns();
+            }
+        }
+    }
+
+    /// Returns the outcome of the last reload that changed something.
+    pub fn last_reload(&self) -> Option<&Reload> {
+        self.last_reload.as_ref()
+    }
+
+    /// Maps a position at the reference resolution to captured frames.
+    pub fn map_point(&self, pos: Point) -> Point {
+        match &self.resolution_profile {
+            Some(profile) => profile.map_point(pos, self.capturer.screen_size()),
+            None => pos,
+        }
+    }
+
+    /// Maps a region at the reference resolution to captured frames.
+    pub fn map_rect(&self, rect: Rect) -> Rect {
+        match &self.resolution_profile {
+            Some(profile) => profile.map_rect(rect, self.capturer.screen_size()),
+            None => rect,
+        }
+    }
+
+    // Longest wait of a worker frame captured after parking the cursor
+    const PARKED_FRAME_TIMEOUT: Duration = Duration::from_secs(1);
+
+    /// Captures a frame into a buffer owned by the context, which is reused by
+    /// every call, moving the cursor as told by the cursor policy.
+    ///
+    /// With a worker set, returns its latest frame without stalling on the
+    /// capture, only waiting for the very first frame. If the cursor is
+    /// parked, waits for a frame captured after parking instead.
+    ///
+    /// Panics if capturing fails, including once the worker has stopped.
+    pub fn frame(&mut self) -> &Screenshot {
+        self.poll_hot_reload();
+
+        let (parked, restore) = match self.cursor_policy {
+            CursorPolicy::Keep => (None, None),
+            CursorPolicy::Park(pos) => {
+                self.simulator.mouse_move_to(pos);
+                (Some(Instant::now()), None)
+            }
+            CursorPolicy::ParkAndRestore(pos) => {
+                let position = self.simulator.cursor_position();
+                self.simulator.mouse_move_to(pos);
+                (Some(Instant::now()), Some(position))
+            }
+        };
+
+        if let Some(worker) = &self.worker {
+            let frame = parked
+                .and_then(|time| worker.next_after(time, Self::PARKED_FRAME_TIMEOUT))
+                .map(Ok)
+                .unwrap_or_else(|| worker.wait_latest())
+                .unwrap_or_else(|error| panic!("{}", error));
+            if let Some(recorder) = &self.recorder {
+                recorder.record_frame(frame.screenshot());
+            }
+            self.latest = Some(frame.into_screenshot());
+        } else {
+            self.capturer.frame_into(&mut self.screenshot);
+        }
+
+        if let Some(position) = restore {
+            self.simulator.mouse_move_to(position);
+        }
+
+        match (&self.worker, &self.latest) {
+            (Some(_), Some(latest)) => latest,
+            _ => &self.screenshot,
+        }
+    }
+
+    pub fn recorder(&self) -> Option<&Recorder> {
+        self.recorder.as_ref()
+    }
+
+    /// Records captured frames and simulated actions into `dir`, see
+    /// `Recorder`. State transitions are recorded by `record_transition`.
+    pub fn enable_recording<P: AsRef<Path>>(&mut self, dir: P) -> Result<()> {
+        self.disable_recording()?;
+        let recorder = Recorder::create(dir)?;
+        self.capturer.set_recorder(Some(recorder.clone()));
+        self.simulator.set_recorder(Some(recorder.clone()));
+        self.recorder = Some(recorder);
+        Ok(())
+    }
+
+    /// Stops recording and waits for the recording to be written.
+    pub fn disable_recording(&mut self) -> Result<()> {
+        self.capturer.set_recorder(None);
+        self.simulator.set_recorder(None);
+        match self.recorder.take() {
+            Some(recorder) => recorder.finish(),
+            None => Ok(()),
+        }
+    }
+
+    /// Records a state transition if recording, meant to be called from the
+    /// transition hook of an `Fsm`:
+    ///
+    /// ```no_run
+    /// use automation::context::Context;
+    /// use automation::fsm::{Fsm, PresetState, PresetTransition};
+    ///
+    /// # fn main() -> automation::Result<()> {
+    /// let mut ctx = Context::new();
+    /// ctx.enable_recording("recording")?;
+    /// let mut fsm: Fsm<Context, PresetState, PresetTransition> =
+    ///     Fsm::new(PresetState::Entry, PresetState::Exit);
+    /// fsm.set_on_transition(Some(Box::new(Context::record_transition)));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn record_transition(&mut self, src: StateId, dst: StateId) {
+        if let Some(recorder) = &self.recorder {
+            recorder.record_transition(src, dst);
+        }
+    }
 }
 
 impl Default for Context {
@@ -29,3 +382,195 @@ impl Default for Context {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::cell::{Cell, RefCell};
+    use std::fs;
+    use std::rc::Rc;
+
+    use image::{DynamicImage, GrayImage, ImageOutputFormat, Luma};
+
+    use super::*;
+    use crate::fsm::{PresetState, State};
+    use crate::geometry::Size;
+    use crate::image::{Direction, Finder, PixelFormat};
+    use crate::test_util::TempDir;
+
+    // Shows the horizontal position of the cursor as the luma of the screen
+    struct CursorScreen(Rc<Cell<ScreenPoint>>);
+
+    impl CaptureBackend for CursorScreen {
+        fn screen_size(&self) -> Size {
+            Size::new(1, 1)
+        }
+
+        fn try_frame_into(&mut self, screenshot: &mut Screenshot) -> Result<bool> {
+            let luma = self.0.get().x as u8;
+            screenshot.fill_from_raw(1, 1, 1, PixelFormat::Gray, &[luma])?;
+            Ok(true)
+        }
+    }
+
+    struct CursorInput(Rc<Cell<ScreenPoint>>);
+
+    impl InputBackend for CursorInput {
+        fn cursor_position(&self) -> Result<ScreenPoint> {
+            Ok(self.0.get())
+        }
+
+        fn mouse_move_to(&mut self, pos: ScreenPoint) -> Result<()> {
+            self.0.set(pos);
+            Ok(())
+        }
+
+        fn mouse_move_by(&mut self, dx: i32, dy: i32) -> Result<()> {
+            self.0.set(self.0.get().offset(dx, dy));
+            Ok(())
+        }
+
+        fn mouse_click(&mut self, _: MouseButton) -> Result<()> {
+            Ok(())
+        }
+
+        fn mouse_down(&mut self, _: MouseButton) -> Result<()> {
+            Ok(())
+        }
+
+        fn mouse_up(&mut self, _: MouseButton) -> Result<()> {
+            Ok(())
+        }
+
+        fn mouse_scroll(&mut self, _: i32, _: i32) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn park_cursor_while_capturing() {
+        let cursor = Rc::new(Cell::new(ScreenPoint::new(0, 0)));
+        let mut ctx = Context::from_parts(
+            Capturer::with_backend(CursorScreen(cursor.clone())),
+            Simulator::with_backend(CursorInput(cursor.clone())),
+        );
+        ctx.simulator_mut().mouse_move_to(ScreenPoint::new(50, 60));
+        assert_eq!(ctx.frame().pixel(0, 0).luma(), 50);
+
+        ctx.set_cursor_policy(CursorPolicy::ParkAndRestore(ScreenPoint::new(7, 0)));
+        assert_eq!(ctx.frame().pixel(0, 0).luma(), 7);
+        assert_eq!(
+            ctx.simulator_mut().cursor_position(),
+            ScreenPoint::new(50, 60)
+        );
+
+        ctx.set_cursor_policy(CursorPolicy::Park(ScreenPoint::new(9, 0)));
+        assert_eq!(ctx.frame().pixel(0, 0).luma(), 9);
+        assert_eq!(cursor.get(), ScreenPoint::new(9, 0));
+    }
+
+    // A 200x100 screen of a texture without repeats
+    struct StaticScreen;
+
+    impl StaticScreen {
+        fn luma(x: u32, y: u32) -> u8 {
+            ((x * x * 7 + y * 13 + (x + y) % 31) % 256) as u8
+        }
+    }
+
+    impl CaptureBackend for StaticScreen {
+        fn screen_size(&self) -> Size {
+            Size::new(200, 100)
+        }
+
+        fn try_frame_into(&mut self, screenshot: &mut Screenshot) -> Result<bool> {
+            let lumas: Vec<u8> = (0..100)
+                .flat_map(|y| (0..200).map(move |x| Self::luma(x, y)))
+                .collect();
+            screenshot.fill_from_raw(200, 100, 200, PixelFormat::Gray, &lumas)?;
+            Ok(true)
+        }
+    }
+
+    fn static_context(cursor: Rc<Cell<ScreenPoint>>) -> Context {
+        Context::from_parts(
+            Capturer::with_backend(StaticScreen),
+            Simulator::with_backend(CursorInput(cursor)),
+        )
+    }
+
+    #[test]
+    fn load_pattern_for_screen() {
+        let mut ctx = static_context(Rc::new(Cell::new(ScreenPoint::new(0, 0))));
+
+        // The item at (60, 40) cropped at twice the resolution of the screen
+        let image = GrayImage::from_fn(80, 40, |x, y| {
+            Luma([StaticScreen::luma(60 + x / 2, 40 + y / 2)])
+        });
+        let mut png = vec![];
+        DynamicImage::ImageLuma8(image)
+            .write_to(&mut png, ImageOutputFormat::Png)
+            .unwrap();
+        ctx.set_resolution_profile(Some(ResolutionProfile::new(400, 200)));
+        let pattern = ctx.load_pattern(&png).unwrap();
+        assert_eq!((pattern.size(), pattern.scale()), (Size::new(40, 20), 0.5));
+        let frame = ctx.frame();
+        assert_eq!(
+            Finder::new(frame).find(&pattern, Direction::Down),
+            Some(Point::new(80, 50))
+        );
+
+        // Sizes are kept to a pixel at least
+        ctx.set_resolution_profile(Some(ResolutionProfile::new(16000, 8000)));
+        let pattern = ctx.load_pattern(&png).unwrap();
+        assert_eq!(pattern.size(), Size::new(1, 1));
+    }
+
+    #[test]
+    fn hot_reload_named_patterns() {
+        let dir = TempDir::new("hot-reload");
+        let save_item = |rect: Rect| {
+            GrayImage::from_fn(rect.width, rect.height, |x, y| {
+                Luma([StaticScreen::luma(rect.x + x, rect.y + y)])
+            })
+            .save(dir.join("item.png"))
+            .unwrap();
+        };
+        save_item(Rect::new(60, 40, 40, 20));
+
+        let cursor = Rc::new(Cell::new(ScreenPoint::new(0, 0)));
+        let mut ctx = static_context(cursor.clone());
+        ctx.set_pattern_library(Some(ctx.load_library(&dir).unwrap()));
+        ctx.set_hot_reload(Some(Duration::from_secs(0)));
+        let reloads = Rc::new(RefCell::new(vec![]));
+        {
+            let reloads = reloads.clone();
+            ctx.set_on_reload(Some(Box::new(move |reload: &Reload| {
+                reloads.borrow_mut().push(reload.clone())
+            })));
+        }
+
+        let mut state = PresetState::MouseMoveTo {
+            pattern: "item".into(),
+            dir: Direction::Down,
+            offset: (0, 0),
+        };
+        assert!(state.tick(&mut ctx));
+        assert_eq!(cursor.get(), ScreenPoint::new(80, 50));
+
+        // The first tick after the change moves to the new item
+        save_item(Rect::new(120, 60, 32, 20));
+        assert!(state.tick(&mut ctx));
+        assert_eq!(cursor.get(), ScreenPoint::new(136, 70));
+        assert_eq!(reloads.borrow().len(), 1);
+        assert_eq!(reloads.borrow()[0].reloaded(), ["item"]);
+
+        // A broken sidecar is reported while the pattern is kept
+        fs::write(dir.join("item.meta"), "threshold = loose\n").unwrap();
+        cursor.set(ScreenPoint::new(0, 0));
+        assert!(state.tick(&mut ctx));
+        assert_eq!(cursor.get(), ScreenPoint::new(136, 70));
+        let errors = ctx.last_reload().unwrap().errors();
+        assert!(errors[0].contains("item.meta line 1"), "{:?}", errors);
+        assert_eq!(reloads.borrow().len(), 2);
+    }
+}
@@ -0,0 +1,80 @@
+use crate::geometry::{Point, Rect, Size};
+
+/// Resolution of the screen a script was authored at, so that its patterns
+/// and coordinates are mapped to screens of other resolutions.
+///
+/// The layout is assumed to scale with the screen. Patterns keep their aspect
+/// ratio and are scaled by the smaller of the horizontal and vertical factors.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ResolutionProfile {
+    width: u32,
+    height: u32,
+}
+
+impl ResolutionProfile {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self { width, height }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Horizontal and vertical factors from the reference resolution to
+    /// `size`.
+    pub fn scale_to(&self, size: Size) -> (f64, f64) {
+        (
+            size.width as f64 / self.width as f64,
+            size.height as f64 / self.height as f64,
+        )
+    }
+
+    /// Factor to resize patterns by for a screen of `size`.
+    pub fn pattern_scale_to(&self, size: Size) -> f64 {
+        let (scale_x, scale_y) = self.scale_to(size);
+        scale_x.min(scale_y)
+    }
+
+    /// Maps a position at the reference resolution to a screen of `size`.
+    pub fn map_point(&self, pos: Point, size: Size) -> Point {
+        let (scale_x, scale_y) = self.scale_to(size);
+        pos.scale(scale_x, scale_y)
+    }
+
+    /// Maps a region at the reference resolution to a screen of `size`,
+    /// covering every pixel the region partly covers.
+    pub fn map_rect(&self, rect: Rect, size: Size) -> Rect {
+        let (scale_x, scale_y) = self.scale_to(size);
+        let x = (rect.x as f64 * scale_x).floor() as u32;
+        let y = (rect.y as f64 * scale_y).floor() as u32;
+        let xx = (rect.right() as f64 * scale_x).ceil() as u32;
+        let yy = (rect.bottom() as f64 * scale_y).ceil() as u32;
+        Rect::new(x, y, xx - x, yy - y)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn map_to_larger_screen() {
+        let profile = ResolutionProfile::new(1920, 1080);
+        let size = Size::new(2560, 1440);
+        assert_eq!(
+            profile.map_point(Point::new(960, 540), size),
+            Point::new(1280, 720)
+        );
+        assert_eq!(
+            profile.map_rect(Rect::new(10, 10, 100, 50), size),
+            Rect::new(13, 13, 134, 67)
+        );
+
+        // Patterns keep their aspect ratio on a wider screen
+        assert_eq!(profile.pattern_scale_to(Size::new(3840, 1440)), 4.0 / 3.0);
+    }
+}
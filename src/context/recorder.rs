@@ -0,0 +1,191 @@
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::fsm::StateId;
+use crate::image::Screenshot;
+use crate::Result;
+
+/// Name of the manifest in a recording directory.
+pub const MANIFEST: &str = "manifest.txt";
+
+enum Entry {
+    Frame(Screenshot),
+    Input(String),
+    Transition(StateId, StateId),
+}
+
+struct Inner {
+    start: Instant,
+    last_frame: Mutex<Option<Screenshot>>,
+    sender: Mutex<Option<SyncSender<(Duration, Entry)>>>,
+    dropped_frames: AtomicU64,
+    writer: Mutex<Option<JoinHandle<Result<()>>>>,
+}
+
+impl Inner {
+    fn finish(&self) -> Result<()> {
+        self.sender.lock().unwrap().take();
+        match self.writer.lock().unwrap().take() {
+            Some(writer) => writer
+                .join()
+                .unwrap_or_else(|_| Err("Recording writer panicked".to_string())),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for Inner {
+    fn drop(&mut self) {
+        let _ = self.finish();
+    }
+}
+
+/// Records a session into a directory: captured frames as PNG files, with
+/// unchanged frames skipped, and a manifest listing the frames, simulated
+/// actions and state transitions with the milliseconds since the start.
+///
+/// Files are written on a background thread. Frames arriving while too many
+/// are waiting to be written are dropped, see `dropped_frames`. Clones share
+/// the same recording.
+#[derive(Clone)]
+pub struct Recorder {
+    inner: Arc<Inner>,
+}
+
+impl Recorder {
+    // Entries waiting to be written at most
+    const QUEUE_LEN: usize = 64;
+
+    pub fn create<P: AsRef<Path>>(dir: P) -> Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir).map_err(|err| err.to_string())?;
+        let mut manifest =
+            BufWriter::new(File::create(dir.join(MANIFEST)).map_err(|err| err.to_string())?);
+        let started = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        writeln!(manifest, "# started {}", started.as_millis()).map_err(|err| err.to_string())?;
+
+        let (sender, receiver) = mpsc::sync_channel::<(Duration, Entry)>(Self::QUEUE_LEN);
+        let writer = thread::spawn(move || {
+            let mut frame_count = 0u32;
+            for (time, entry) in receiver {
+                let time = time.as_millis();
+                match entry {
+                    Entry::Frame(screenshot) => {
+                        frame_count += 1;
+                        let name = format!("frame_{:06}.png", frame_count);
+                        screenshot.save(dir.join(&name))?;
+                        writeln!(manifest, "{}\tframe\t{}", time, name)
+                    }
+                    Entry::Input(action) => writeln!(manifest, "{}\tinput\t{}", time, action),
+                    Entry::Transition(src, dst) => {
+                        writeln!(manifest, "{}\ttransition\t{:?} {:?}", time, src, dst)
+                    }
+                }
+                .map_err(|err| err.to_string())?;
+            }
+            manifest.flush().map_err(|err| err.to_string())
+        });
+
+        Ok(Self {
+            inner: Arc::new(Inner {
+                start: Instant::now(),
+                last_frame: Mutex::new(None),
+                sender: Mutex::new(Some(sender)),
+                dropped_frames: AtomicU64::new(0),
+                writer: Mutex::new(Some(writer)),
+            }),
+        })
+    }
+
+    fn send(&self, entry: Entry) {
+        if let Some(sender) = &*self.inner.sender.lock().unwrap() {
+            // The writer only stops on errors, reported by `finish`
+            let _ = sender.send((self.inner.start.elapsed(), entry));
+        }
+    }
+
+    /// Records a frame unless it equals the last recorded one, or drops it if
+    /// the writer is behind.
+    pub fn record_frame(&self, screenshot: &Screenshot) {
+        let mut last_frame = self.inner.last_frame.lock().unwrap();
+        if let Some(last_frame) = &*last_frame {
+            if screenshot.diff(last_frame, None).is_empty() {
+                return;
+            }
+        }
+
+        let mut frame = Screenshot::default();
+        frame.copy_from(screenshot);
+        if let Some(sender) = &*self.inner.sender.lock().unwrap() {
+            let entry = (self.inner.start.elapsed(), Entry::Frame(frame));
+            match sender.try_send(entry) {
+                Ok(()) => {}
+                Err(TrySendError::Full(_)) => {
+                    self.inner.dropped_frames.fetch_add(1, Ordering::Relaxed);
+                    return;
+                }
+                // The writer only stops on errors, reported by `finish`
+                Err(TrySendError::Disconnected(_)) => return,
+            }
+        }
+        last_frame
+            .get_or_insert_with(Default::default)
+            .copy_from(screenshot);
+    }
+
+    /// Number of frames dropped because the writer was behind.
+    pub fn dropped_frames(&self) -> u64 {
+        self.inner.dropped_frames.load(Ordering::Relaxed)
+    }
+
+    pub fn record_input(&self, action: fmt::Arguments) {
+        self.send(Entry::Input(action.to_string()));
+    }
+
+    pub fn record_transition(&self, src: StateId, dst: StateId) {
+        self.send(Entry::Transition(src, dst));
+    }
+
+    /// Stops recording and waits for everything to be written, for all clones.
+    pub fn finish(&self) -> Result<()> {
+        self.inner.finish()
+    }
+}
+
+/// A frame listed in the manifest of a recording.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RecordedFrame {
+    pub time: Duration,
+    pub path: PathBuf,
+}
+
+/// Reads the frames listed in the manifest of a recording directory.
+pub fn read_recorded_frames<P: AsRef<Path>>(dir: P) -> Result<Vec<RecordedFrame>> {
+    let dir = dir.as_ref();
+    let manifest = fs::read_to_string(dir.join(MANIFEST)).map_err(|err| err.to_string())?;
+    let mut frames = vec![];
+    for line in manifest.lines().filter(|line| !line.starts_with('#')) {
+        let mut fields = line.splitn(3, '\t');
+        if let (Some(time), Some("frame"), Some(name)) =
+            (fields.next(), fields.next(), fields.next())
+        {
+            let time = time
+                .parse()
+                .map_err(|_| format!("Invalid time in manifest line: {}", line))?;
+            frames.push(RecordedFrame {
+                time: Duration::from_millis(time),
+                path: dir.join(name),
+            });
+        }
+    }
+    Ok(frames)
+}
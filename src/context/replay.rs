@@ -0,0 +1,117 @@
+use std::fs;
+use std::path::Path;
+use std::time::Instant;
+
+use crate::geometry::Size;
+use crate::image::Screenshot;
+use crate::Result;
+
+use super::{read_recorded_frames, CaptureBackend, RecordedFrame};
+
+/// Feeds the frames of a directory written by a `Recorder` back to a
+/// `Capturer`. The last frame is repeated once the recording is over.
+pub struct ReplayCapture {
+    frames: Vec<RecordedFrame>,
+    paced: bool,
+    start: Option<Instant>,
+    next: usize,
+    size: Size,
+    loaded: Option<(usize, Screenshot)>,
+}
+
+impl ReplayCapture {
+    /// Replays one recorded frame per capture.
+    pub fn open<P: AsRef<Path>>(dir: P) -> Result<Self> {
+        let frames = read_recorded_frames(dir)?;
+        let first = frames
+            .first()
+            .ok_or_else(|| "Recording has no frames".to_string())?;
+        let size = image::image_dimensions(&first.path).map_err(|err| err.to_string())?;
+        Ok(Self {
+            frames,
+            paced: false,
+            start: None,
+            next: 0,
+            size: size.into(),
+            loaded: None,
+        })
+    }
+
+    /// Replays the frames at their recorded times, counted from the first
+    /// capture.
+    pub fn open_paced<P: AsRef<Path>>(dir: P) -> Result<Self> {
+        let mut replay = Self::open(dir)?;
+        replay.paced = true;
+        Ok(replay)
+    }
+
+    fn load(&mut self, index: usize) -> Result<&Screenshot> {
+        if !matches!(&self.loaded, Some((loaded, _)) if *loaded == index) {
+            let buf = fs::read(&self.frames[index].path).map_err(|err| err.to_string())?;
+            self.loaded = Some((index, Screenshot::from_file_buf(&buf)?));
+        }
+        Ok(&self.loaded.as_ref().unwrap().1)
+    }
+}
+
+impl CaptureBackend for ReplayCapture {
+    fn screen_size(&self) -> Size {
+        self.size
+    }
+
+    fn try_frame_into(&mut self, screenshot: &mut Screenshot) -> Result<bool> {
+        let index = if self.paced {
+            let elapsed = self.start.get_or_insert_with(Instant::now).elapsed();
+            let first = self.frames[0].time;
+            self.frames
+                .iter()
+                .rposition(|frame| frame.time - first <= elapsed)
+                .unwrap_or(0)
+        } else {
+            let index = self.next.min(self.frames.len() - 1);
+            self.next += 1;
+            index
+        };
+        screenshot.copy_from(self.load(index)?);
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::Recorder;
+    use super::*;
+    use crate::image::PixelFormat;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn replay_recorded_frames() {
+        let dir = TempDir::new("replay");
+        let frames: Vec<_> = [[1u8, 2, 3, 4], [1, 2, 3, 4], [5, 6, 7, 8]]
+            .iter()
+            .map(|lumas| Screenshot::from_raw(2, 2, 2, PixelFormat::Gray, lumas).unwrap())
+            .collect();
+
+        let recorder = Recorder::create(&dir).unwrap();
+        for frame in &frames {
+            recorder.record_frame(frame);
+        }
+        recorder.record_input(format_args!("mouse_click Left"));
+        recorder.finish().unwrap();
+
+        // The unchanged frame is skipped
+        let recorded = read_recorded_frames(&dir).unwrap();
+        assert_eq!(recorded.len(), 2);
+
+        let mut replay = ReplayCapture::open(&dir).unwrap();
+        assert_eq!(replay.screen_size(), Size::new(2, 2));
+        let mut screenshot = Screenshot::default();
+        for expected in [&frames[0], &frames[2], &frames[2]] {
+            assert!(replay.try_frame_into(&mut screenshot).unwrap());
+            assert!(screenshot.diff(expected, None).is_empty());
+        }
+
+        let manifest = fs::read_to_string(dir.join(super::super::MANIFEST)).unwrap();
+        assert!(manifest.contains("\tinput\tmouse_click Left"));
+    }
+}
@@ -1,4 +1,13 @@
-use tfc::{Context, MouseContext};
+use std::fmt;
+
+use crate::geometry::ScreenPoint;
+use crate::Result;
+
+#[cfg(not(target_os = "linux"))]
+use super::DesktopInput;
+use super::Recorder;
+#[cfg(target_os = "linux")]
+use super::X11Input;
 
 #[derive(Clone, Copy, Debug)]
 pub enum MouseButton {
@@ -7,40 +16,105 @@ pub enum MouseButton {
     Right,
 }
 
+/// Target of the actions simulated by a `Simulator`.
+pub trait InputBackend {
+    /// Returns the position of the cursor, or the last one moved to where
+    /// it can not be queried.
+    fn cursor_position(&self) -> Result<ScreenPoint>;
+
+    fn mouse_move_to(&mut self, pos: ScreenPoint) -> Result<()>;
+
+    fn mouse_move_by(&mut self, dx: i32, dy: i32) -> Result<()>;
+
+    fn mouse_click(&mut self, btn: MouseButton) -> Result<()>;
+
+    fn mouse_down(&mut self, btn: MouseButton) -> Result<()>;
+
+    fn mouse_up(&mut self, btn: MouseButton) -> Result<()>;
+
+    /// Scrolls by `dx` and `dy` steps, positive values scrolling right and
+    /// down.
+    fn mouse_scroll(&mut self, dx: i32, dy: i32) -> Result<()>;
+}
+
 pub struct Simulator {
-    context: Context,
+    backend: Box<dyn InputBackend>,
+    recorder: Option<Recorder>,
 }
 
 impl Simulator {
+    /// Simulates input on the desktop, through X11 on Linux.
     pub fn new() -> Self {
-        let context = Context::new().expect("Failed to get context");
-        Self { context }
+        #[cfg(target_os = "linux")]
+        let backend = X11Input::connect(None).expect("Failed to connect to X server");
+        #[cfg(not(target_os = "linux"))]
+        let backend = DesktopInput::new();
+        Self::with_backend(backend)
+    }
+
+    pub fn with_backend<B: InputBackend + 'static>(backend: B) -> Self {
+        Self {
+            backend: Box::new(backend),
+            recorder: None,
+        }
+    }
+
+    /// Records every simulated action, or stops recording if `None`.
+    pub fn set_recorder(&mut self, recorder: Option<Recorder>) {
+        self.recorder = recorder;
     }
 
-    pub fn mouse_move_to(&mut self, x: u32, y: u32) {
-        self.context
-            .mouse_move_abs(x as i32, y as i32)
+    fn record(&self, action: fmt::Arguments) {
+        if let Some(recorder) = &self.recorder {
+            recorder.record_input(action);
+        }
+    }
+
+    pub fn cursor_position(&self) -> ScreenPoint {
+        self.backend
+            .cursor_position()
+            .expect("Failed to get cursor position")
+    }
+
+    pub fn mouse_move_to(&mut self, pos: ScreenPoint) {
+        self.record(format_args!("mouse_move_to {} {}", pos.x, pos.y));
+        self.backend
+            .mouse_move_to(pos)
             .expect("Failed to simulate mouse moving");
     }
 
     pub fn mouse_move_by(&mut self, dx: i32, dy: i32) {
-        self.context
-            .mouse_move_rel(dx, dy)
+        self.record(format_args!("mouse_move_by {} {}", dx, dy));
+        self.backend
+            .mouse_move_by(dx, dy)
             .expect("Failed to simulate mouse moving");
     }
 
     pub fn mouse_click(&mut self, btn: MouseButton) {
-        self.context
-            .mouse_click(match btn {
-                MouseButton::Left => tfc::MouseButton::Left,
-                MouseButton::Middle => tfc::MouseButton::Middle,
-                MouseButton::Right => tfc::MouseButton::Right,
-            })
+        self.record(format_args!("mouse_click {:?}", btn));
+        self.backend
+            .mouse_click(btn)
             .expect("Failed to simulate mouse click");
     }
 
+    /// Presses a button until `mouse_up`, moving in between to drag.
+    pub fn mouse_down(&mut self, btn: MouseButton) {
+        self.record(format_args!("mouse_down {:?}", btn));
+        self.backend
+            .mouse_down(btn)
+            .expect("Failed to simulate mouse pressing");
+    }
+
+    pub fn mouse_up(&mut self, btn: MouseButton) {
+        self.record(format_args!("mouse_up {:?}", btn));
+        self.backend
+            .mouse_up(btn)
+            .expect("Failed to simulate mouse releasing");
+    }
+
     pub fn mouse_scroll(&mut self, dx: i32, dy: i32) {
-        self.context
+        self.record(format_args!("mouse_scroll {} {}", dx, dy));
+        self.backend
             .mouse_scroll(dx, dy)
             .expect("Failed to simulate mouse scrolling");
     }
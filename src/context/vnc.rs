@@ -0,0 +1,670 @@
+use std::convert::TryFrom;
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use crate::geometry::{ScreenPoint, Size};
+use crate::image::{PixelFormat, Screenshot};
+use crate::Result;
+
+use super::{CaptureBackend, InputBackend, MouseButton};
+
+// Message types of the RFB protocol
+const SET_PIXEL_FORMAT: u8 = 0;
+const SET_ENCODINGS: u8 = 2;
+const FRAMEBUFFER_UPDATE_REQUEST: u8 = 3;
+const KEY_EVENT: u8 = 4;
+const POINTER_EVENT: u8 = 5;
+const FRAMEBUFFER_UPDATE: u8 = 0;
+const SET_COLOUR_MAP_ENTRIES: u8 = 1;
+const BELL: u8 = 2;
+const SERVER_CUT_TEXT: u8 = 3;
+
+// Encodings, where the desktop size is a pseudo-encoding to follow resizing
+const RAW: i32 = 0;
+const COPY_RECT: i32 = 1;
+const DESKTOP_SIZE: i32 = -223;
+
+// 32 bits little endian true colour, read as BGRX
+const PIXEL_FORMAT: [u8; 16] = [32, 24, 0, 1, 0, 255, 0, 255, 0, 255, 16, 8, 0, 0, 0, 0];
+
+fn read_bytes<R: Read>(reader: &mut R, len: usize) -> io::Result<Vec<u8>> {
+    let mut buf = vec![0; len];
+    reader.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+fn read_u8<R: Read>(reader: &mut R) -> io::Result<u8> {
+    let mut buf = [0; 1];
+    reader.read_exact(&mut buf)?;
+    Ok(buf[0])
+}
+
+fn read_u16<R: Read>(reader: &mut R) -> io::Result<u16> {
+    let mut buf = [0; 2];
+    reader.read_exact(&mut buf)?;
+    Ok(u16::from_be_bytes(buf))
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> io::Result<u32> {
+    let mut buf = [0; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_be_bytes(buf))
+}
+
+fn read_reason<R: Read>(reader: &mut R) -> Result<String> {
+    let len = read_u32(reader).map_err(|err| err.to_string())?;
+    let reason = read_bytes(reader, len as usize).map_err(|err| err.to_string())?;
+    Ok(String::from_utf8_lossy(&reason).into_owned())
+}
+
+/// Negotiates the protocol version and security, returning the size of the
+/// framebuffer. Only servers without authentication are supported.
+fn handshake(stream: &mut TcpStream) -> Result<(u32, u32)> {
+    let version = read_bytes(stream, 12).map_err(|err| err.to_string())?;
+    let version = String::from_utf8_lossy(&version);
+    let minor = match version
+        .strip_prefix("RFB 003.")
+        .and_then(|minor| minor.trim_end().parse::<u32>().ok())
+    {
+        Some(minor) if minor >= 8 => 8,
+        Some(7) => 7,
+        Some(_) => 3,
+        None => return Err(format!("Unsupported RFB version {:?}", version)),
+    };
+    stream
+        .write_all(format!("RFB 003.{:03}\n", minor).as_bytes())
+        .map_err(|err| err.to_string())?;
+
+    let security = if minor == 3 {
+        read_u32(stream).map_err(|err| err.to_string())?
+    } else {
+        let count = read_u8(stream).map_err(|err| err.to_string())?;
+        if count == 0 {
+            0
+        } else {
+            let types = read_bytes(stream, count as usize).map_err(|err| err.to_string())?;
+            if types.contains(&1) {
+                stream.write_all(&[1]).map_err(|err| err.to_string())?;
+                1
+            } else {
+                types[0] as u32
+            }
+        }
+    };
+    match security {
+        0 => return Err(format!("VNC server refused: {}", read_reason(stream)?)),
+        1 => {}
+        _ => return Err("VNC server requires an unsupported authentication".to_string()),
+    }
+    if minor == 8 && read_u32(stream).map_err(|err| err.to_string())? != 0 {
+        return Err(format!(
+            "VNC security handshake failed: {}",
+            read_reason(stream)?
+        ));
+    }
+
+    // Share the desktop with other clients
+    stream.write_all(&[1]).map_err(|err| err.to_string())?;
+    let width = read_u16(stream).map_err(|err| err.to_string())?;
+    let height = read_u16(stream).map_err(|err| err.to_string())?;
+    read_bytes(stream, 16).map_err(|err| err.to_string())?;
+    read_reason(stream)?;
+    Ok((width as u32, height as u32))
+}
+
+#[derive(Default)]
+struct Framebuffer {
+    width: u32,
+    height: u32,
+    // Pixels in BGRX
+    buf: Vec<u8>,
+    // Whether a complete frame has been received
+    ready: bool,
+    error: Option<String>,
+}
+
+impl Framebuffer {
+    fn resize(&mut self, width: u32, height: u32) {
+        self.width = width;
+        self.height = height;
+        self.buf.clear();
+        self.buf.resize(width as usize * height as usize * 4, 0);
+    }
+
+    fn check_bounds(&self, x: u32, y: u32, width: u32, height: u32) -> io::Result<()> {
+        if x + width > self.width || y + height > self.height {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Rectangle out of the framebuffer",
+            ));
+        }
+        Ok(())
+    }
+
+    fn put(&mut self, x: u32, y: u32, width: u32, pixels: &[u8]) {
+        let row_len = width as usize * 4;
+        for (i, row) in pixels.chunks_exact(row_len).enumerate() {
+            let head = ((y as usize + i) * self.width as usize + x as usize) * 4;
+            self.buf[head..head + row_len].copy_from_slice(row);
+        }
+    }
+
+    fn get(&self, x: u32, y: u32, width: u32, height: u32) -> Vec<u8> {
+        let row_len = width as usize * 4;
+        let mut pixels = Vec::with_capacity(row_len * height as usize);
+        for y in y..y + height {
+            let head = (y as usize * self.width as usize + x as usize) * 4;
+            pixels.extend_from_slice(&self.buf[head..head + row_len]);
+        }
+        pixels
+    }
+}
+
+struct Connection {
+    // Client messages are written whole under the lock
+    writer: Arc<Mutex<TcpStream>>,
+    framebuffer: Arc<Mutex<Framebuffer>>,
+    reader: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl Connection {
+    fn send(&self, message: &[u8]) -> Result<()> {
+        self.writer
+            .lock()
+            .unwrap()
+            .write_all(message)
+            .map_err(|err| err.to_string())
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        let _ = self.writer.lock().unwrap().shutdown(Shutdown::Both);
+        if let Some(reader) = self.reader.lock().unwrap().take() {
+            let _ = reader.join();
+        }
+    }
+}
+
+fn update_request(incremental: bool, width: u32, height: u32) -> Vec<u8> {
+    let mut message = vec![FRAMEBUFFER_UPDATE_REQUEST, incremental as u8, 0, 0, 0, 0];
+    message.extend_from_slice(&(width as u16).to_be_bytes());
+    message.extend_from_slice(&(height as u16).to_be_bytes());
+    message
+}
+
+/// Reads server messages, applying each framebuffer update to a staging
+/// copy and publishing it whole once complete, then requests the next update.
+fn read_messages(
+    mut reader: TcpStream,
+    writer: &Mutex<TcpStream>,
+    framebuffer: &Mutex<Framebuffer>,
+) -> io::Result<()> {
+    let mut staging = Framebuffer::default();
+    {
+        let framebuffer = framebuffer.lock().unwrap();
+        staging.resize(framebuffer.width, framebuffer.height);
+    }
+    loop {
+        match read_u8(&mut reader)? {
+            FRAMEBUFFER_UPDATE => {
+                read_u8(&mut reader)?;
+                let count = read_u16(&mut reader)?;
+                let mut resized = false;
+                for _ in 0..count {
+                    let x = read_u16(&mut reader)? as u32;
+                    let y = read_u16(&mut reader)? as u32;
+                    let width = read_u16(&mut reader)? as u32;
+                    let height = read_u16(&mut reader)? as u32;
+                    let mut encoding = [0; 4];
+                    reader.read_exact(&mut encoding)?;
+                    match i32::from_be_bytes(encoding) {
+                        RAW => {
+                            let len = width as usize * height as usize * 4;
+                            let pixels = read_bytes(&mut reader, len)?;
+                            staging.check_bounds(x, y, width, height)?;
+                            staging.put(x, y, width, &pixels);
+                        }
+                        COPY_RECT => {
+                            let src_x = read_u16(&mut reader)? as u32;
+                            let src_y = read_u16(&mut reader)? as u32;
+                            staging.check_bounds(src_x, src_y, width, height)?;
+                            staging.check_bounds(x, y, width, height)?;
+                            let pixels = staging.get(src_x, src_y, width, height);
+                            staging.put(x, y, width, &pixels);
+                        }
+                        DESKTOP_SIZE => {
+                            staging.resize(width, height);
+                            resized = true;
+                        }
+                        encoding => {
+                            return Err(io::Error::new(
+                                io::ErrorKind::InvalidData,
+                                format!("Unexpected encoding {}", encoding),
+                            ))
+                        }
+                    }
+                }
+
+                // A resized framebuffer is blank until the full update that
+                // is requested for it arrives
+                {
+                    let mut framebuffer = framebuffer.lock().unwrap();
+                    framebuffer.width = staging.width;
+                    framebuffer.height = staging.height;
+                    framebuffer.buf.clone_from(&staging.buf);
+                    framebuffer.ready = !resized;
+                }
+                writer.lock().unwrap().write_all(&update_request(
+                    !resized,
+                    staging.width,
+                    staging.height,
+                ))?;
+            }
+            SET_COLOUR_MAP_ENTRIES => {
+                read_u8(&mut reader)?;
+                read_u16(&mut reader)?;
+                let count = read_u16(&mut reader)?;
+                read_bytes(&mut reader, count as usize * 6)?;
+            }
+            BELL => {}
+            SERVER_CUT_TEXT => {
+                read_bytes(&mut reader, 3)?;
+                let len = read_u32(&mut reader)?;
+                read_bytes(&mut reader, len as usize)?;
+            }
+            message => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Unexpected server message {}", message),
+                ))
+            }
+        }
+    }
+}
+
+/// Connects to a VNC server without authentication, returning a capture
+/// backend following its framebuffer and an input backend sending pointer
+/// and key events to it. The connection is closed once both are dropped.
+///
+/// ```no_run
+/// use automation::context::{connect_vnc, Capturer, Context, Simulator};
+///
+/// # fn main() -> automation::Result<()> {
+/// let (capture, input) = connect_vnc("192.168.1.20:5900")?;
+/// let ctx = Context::from_parts(
+///     Capturer::with_backend(capture),
+///     Simulator::with_backend(input),
+/// );
+/// # Ok(())
+/// # }
+/// ```
+pub fn connect_vnc<A: ToSocketAddrs>(addr: A) -> Result<(VncCapture, VncInput)> {
+    let mut stream = TcpStream::connect(addr).map_err(|err| err.to_string())?;
+    stream.set_nodelay(true).map_err(|err| err.to_string())?;
+    let (width, height) = handshake(&mut stream)?;
+
+    let mut setup = vec![SET_PIXEL_FORMAT, 0, 0, 0];
+    setup.extend_from_slice(&PIXEL_FORMAT);
+    setup.extend_from_slice(&[SET_ENCODINGS, 0, 0, 3]);
+    for encoding in &[RAW, COPY_RECT, DESKTOP_SIZE] {
+        setup.extend_from_slice(&encoding.to_be_bytes());
+    }
+    setup.extend_from_slice(&update_request(false, width, height));
+    stream.write_all(&setup).map_err(|err| err.to_string())?;
+
+    let mut framebuffer = Framebuffer::default();
+    framebuffer.resize(width, height);
+    let framebuffer = Arc::new(Mutex::new(framebuffer));
+    let reader = stream.try_clone().map_err(|err| err.to_string())?;
+    let writer = Arc::new(Mutex::new(stream));
+    let reader = {
+        let writer = writer.clone();
+        let framebuffer = framebuffer.clone();
+        thread::spawn(move || {
+            if let Err(err) = read_messages(reader, &writer, &framebuffer) {
+                framebuffer.lock().unwrap().error = Some(err.to_string());
+            }
+        })
+    };
+
+    let conn = Arc::new(Connection {
+        writer,
+        framebuffer,
+        reader: Mutex::new(Some(reader)),
+    });
+    Ok((
+        VncCapture { conn: conn.clone() },
+        VncInput {
+            conn,
+            position: (0, 0),
+            buttons: 0,
+        },
+    ))
+}
+
+/// Captures the framebuffer of a VNC server, see `connect_vnc`.
+///
+/// Updates are received on a background thread, so a capture returns the
+/// latest complete framebuffer.
+pub struct VncCapture {
+    conn: Arc<Connection>,
+}
+
+impl CaptureBackend for VncCapture {
+    fn screen_size(&self) -> Size {
+        let framebuffer = self.conn.framebuffer.lock().unwrap();
+        Size::new(framebuffer.width, framebuffer.height)
+    }
+
+    fn try_frame_into(&mut self, screenshot: &mut Screenshot) -> Result<bool> {
+        let framebuffer = self.conn.framebuffer.lock().unwrap();
+        if let Some(error) = &framebuffer.error {
+            return Err(format!("VNC connection failed: {}", error));
+        }
+        if !framebuffer.ready {
+            return Ok(false);
+        }
+        let (width, height) = (framebuffer.width, framebuffer.height);
+        screenshot.fill_from_raw(
+            width,
+            height,
+            width as usize * 4,
+            PixelFormat::Bgrx,
+            &framebuffer.buf,
+        )?;
+        Ok(true)
+    }
+}
+
+/// Sends pointer and key events to a VNC server, see `connect_vnc`.
+///
+/// `InputBackend` has no keyboard, so keys are sent with `key_down`,
+/// `key_up` and `key_press` on the backend itself.
+pub struct VncInput {
+    conn: Arc<Connection>,
+    position: (u16, u16),
+    buttons: u8,
+}
+
+impl VncInput {
+    // Button masks, where the wheel is reported as buttons
+    const LEFT: u8 = 1;
+    const MIDDLE: u8 = 1 << 1;
+    const RIGHT: u8 = 1 << 2;
+    const SCROLL_UP: u8 = 1 << 3;
+    const SCROLL_DOWN: u8 = 1 << 4;
+    const SCROLL_LEFT: u8 = 1 << 5;
+    const SCROLL_RIGHT: u8 = 1 << 6;
+
+    fn button(btn: MouseButton) -> u8 {
+        match btn {
+            MouseButton::Left => Self::LEFT,
+            MouseButton::Middle => Self::MIDDLE,
+            MouseButton::Right => Self::RIGHT,
+        }
+    }
+
+    fn send_pointer(&self, buttons: u8) -> Result<()> {
+        let mut message = vec![POINTER_EVENT, buttons];
+        message.extend_from_slice(&self.position.0.to_be_bytes());
+        message.extend_from_slice(&self.position.1.to_be_bytes());
+        self.conn.send(&message)
+    }
+
+    fn click(&mut self, button: u8) -> Result<()> {
+        self.send_pointer(self.buttons | button)?;
+        self.send_pointer(self.buttons)
+    }
+
+    fn send_key(&self, keysym: u32, down: bool) -> Result<()> {
+        let mut message = vec![KEY_EVENT, down as u8, 0, 0];
+        message.extend_from_slice(&keysym.to_be_bytes());
+        self.conn.send(&message)
+    }
+
+    /// Presses the key of an X11 keysym, such as `0xff0d` for Return.
+    pub fn key_down(&mut self, keysym: u32) -> Result<()> {
+        self.send_key(keysym, true)
+    }
+
+    /// Releases the key of an X11 keysym.
+    pub fn key_up(&mut self, keysym: u32) -> Result<()> {
+        self.send_key(keysym, false)
+    }
+
+    /// Presses and releases the key of an X11 keysym.
+    pub fn key_press(&mut self, keysym: u32) -> Result<()> {
+        self.key_down(keysym)?;
+        self.key_up(keysym)
+    }
+}
+
+impl InputBackend for VncInput {
+    fn cursor_position(&self) -> Result<ScreenPoint> {
+        Ok(ScreenPoint::new(
+            self.position.0 as i32,
+            self.position.1 as i32,
+        ))
+    }
+
+    /// The framebuffer has no negative positions, which are moved to its
+    /// edge.
+    fn mouse_move_to(&mut self, pos: ScreenPoint) -> Result<()> {
+        self.position = (
+            u16::try_from(pos.x.max(0)).unwrap_or(u16::MAX),
+            u16::try_from(pos.y.max(0)).unwrap_or(u16::MAX),
+        );
+        self.send_pointer(self.buttons)
+    }
+
+    fn mouse_move_by(&mut self, dx: i32, dy: i32) -> Result<()> {
+        let (width, height) = {
+            let framebuffer = self.conn.framebuffer.lock().unwrap();
+            (framebuffer.width as i32, framebuffer.height as i32)
+        };
+        let x = (self.position.0 as i32 + dx).clamp(0, (width - 1).max(0));
+        let y = (self.position.1 as i32 + dy).clamp(0, (height - 1).max(0));
+        self.mouse_move_to(ScreenPoint::new(x, y))
+    }
+
+    fn mouse_click(&mut self, btn: MouseButton) -> Result<()> {
+        self.click(Self::button(btn))
+    }
+
+    fn mouse_down(&mut self, btn: MouseButton) -> Result<()> {
+        self.buttons |= Self::button(btn);
+        self.send_pointer(self.buttons)
+    }
+
+    fn mouse_up(&mut self, btn: MouseButton) -> Result<()> {
+        self.buttons &= !Self::button(btn);
+        self.send_pointer(self.buttons)
+    }
+
+    fn mouse_scroll(&mut self, dx: i32, dy: i32) -> Result<()> {
+        let horizontal = if dx < 0 {
+            Self::SCROLL_LEFT
+        } else {
+            Self::SCROLL_RIGHT
+        };
+        for _ in 0..dx.unsigned_abs() {
+            self.click(horizontal)?;
+        }
+        let vertical = if dy < 0 {
+            Self::SCROLL_UP
+        } else {
+            Self::SCROLL_DOWN
+        };
+        for _ in 0..dy.unsigned_abs() {
+            self.click(vertical)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+
+    use super::super::{Capturer, Simulator};
+    use super::*;
+
+    fn rect(x: u16, y: u16, width: u16, height: u16, encoding: i32) -> Vec<u8> {
+        let mut rect = vec![];
+        for value in &[x, y, width, height] {
+            rect.extend_from_slice(&value.to_be_bytes());
+        }
+        rect.extend_from_slice(&encoding.to_be_bytes());
+        rect
+    }
+
+    #[derive(Debug, PartialEq)]
+    enum Event {
+        Pointer(u8, u16, u16),
+        Key(bool, u32),
+    }
+
+    // Serves a 4x2 framebuffer, first in raw encoding then with its left
+    // half copied to the right, then resizes it to 2x2 and reports the input
+    // events
+    fn serve(listener: TcpListener, events: mpsc::Sender<Event>) {
+        let (mut stream, _) = listener.accept().unwrap();
+        stream.write_all(b"RFB 003.008\n").unwrap();
+        assert_eq!(read_bytes(&mut stream, 12).unwrap(), b"RFB 003.008\n");
+        stream.write_all(&[1, 1]).unwrap();
+        assert_eq!(read_u8(&mut stream).unwrap(), 1);
+        stream.write_all(&0u32.to_be_bytes()).unwrap();
+        assert_eq!(read_u8(&mut stream).unwrap(), 1);
+
+        let mut server_init = vec![0, 4, 0, 2];
+        server_init.extend_from_slice(&[0; 16]);
+        server_init.extend_from_slice(&4u32.to_be_bytes());
+        server_init.extend_from_slice(b"test");
+        stream.write_all(&server_init).unwrap();
+
+        let mut updates = 0;
+        loop {
+            let message = match read_u8(&mut stream) {
+                Ok(message) => message,
+                Err(_) => return,
+            };
+            match message {
+                SET_PIXEL_FORMAT => {
+                    let format = read_bytes(&mut stream, 19).unwrap();
+                    assert_eq!(format[3..], PIXEL_FORMAT);
+                }
+                SET_ENCODINGS => {
+                    read_u8(&mut stream).unwrap();
+                    let count = read_u16(&mut stream).unwrap();
+                    read_bytes(&mut stream, count as usize * 4).unwrap();
+                }
+                FRAMEBUFFER_UPDATE_REQUEST => {
+                    let request = read_bytes(&mut stream, 9).unwrap();
+                    updates += 1;
+                    let mut update = vec![FRAMEBUFFER_UPDATE, 0, 0, 1];
+                    match updates {
+                        1 => {
+                            update.extend(rect(0, 0, 4, 2, RAW));
+                            for luma in 0..8 {
+                                update.extend_from_slice(&[luma * 10, luma * 10, luma * 10, 0]);
+                            }
+                        }
+                        2 => {
+                            update.extend(rect(2, 0, 2, 2, COPY_RECT));
+                            update.extend_from_slice(&[0, 0, 0, 0]);
+                        }
+                        3 => update.extend(rect(0, 0, 2, 2, DESKTOP_SIZE)),
+                        4 => {
+                            // The whole resized framebuffer is requested
+                            assert_eq!(request[0], 0);
+                            assert_eq!(request[5..], [0, 2, 0, 2]);
+                            update[3] = 2;
+                            update.extend(rect(0, 0, 2, 1, RAW));
+                            update.extend_from_slice(&[90; 8]);
+                            update.extend(rect(0, 1, 2, 1, RAW));
+                            update.extend_from_slice(&[90; 8]);
+                        }
+                        _ => continue,
+                    }
+                    stream.write_all(&update).unwrap();
+                }
+                POINTER_EVENT => {
+                    let buttons = read_u8(&mut stream).unwrap();
+                    let x = read_u16(&mut stream).unwrap();
+                    let y = read_u16(&mut stream).unwrap();
+                    events.send(Event::Pointer(buttons, x, y)).unwrap();
+                }
+                KEY_EVENT => {
+                    let down = read_u8(&mut stream).unwrap();
+                    read_u16(&mut stream).unwrap();
+                    let keysym = read_u32(&mut stream).unwrap();
+                    events.send(Event::Key(down == 1, keysym)).unwrap();
+                }
+                message => panic!("Unexpected client message {}", message),
+            }
+        }
+    }
+
+    fn lumas(screenshot: &Screenshot) -> Vec<u8> {
+        let width = screenshot.width();
+        (0..screenshot.height())
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| screenshot.pixel(x, y).luma())
+            .collect()
+    }
+
+    #[test]
+    fn capture_and_click_through_vnc() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (sender, events) = mpsc::channel();
+        let server = thread::spawn(move || serve(listener, sender));
+
+        let (capture, mut input) = connect_vnc(addr).unwrap();
+        let mut capturer = Capturer::with_backend(capture);
+
+        // Updates may be skipped, but never seen in part, out of order or
+        // blank after the resize
+        let frames = [
+            vec![0, 10, 20, 30, 40, 50, 60, 70],
+            vec![0, 10, 0, 10, 40, 50, 40, 50],
+            vec![90, 90, 90, 90],
+        ];
+        let mut screenshot = capturer.frame();
+        assert_eq!(screenshot.pixel(0, 0).a(), u8::MAX);
+        let mut seen = 0;
+        while seen < frames.len() - 1 {
+            let frame = lumas(&screenshot);
+            let index = frames.iter().position(|expected| *expected == frame);
+            seen = index.filter(|&index| index >= seen).unwrap();
+            capturer.frame_into(&mut screenshot);
+        }
+        assert_eq!(capturer.screen_size(), Size::new(2, 2));
+
+        input.key_press(0xff0d).unwrap();
+        let mut simulator = Simulator::with_backend(input);
+        simulator.mouse_move_to(ScreenPoint::new(1, 1));
+        simulator.mouse_click(MouseButton::Right);
+        simulator.mouse_move_by(-10, 0);
+        let events: Vec<_> = events.iter().take(6).collect();
+        assert_eq!(
+            events,
+            vec![
+                Event::Key(true, 0xff0d),
+                Event::Key(false, 0xff0d),
+                Event::Pointer(0, 1, 1),
+                Event::Pointer(4, 1, 1),
+                Event::Pointer(0, 1, 1),
+                Event::Pointer(0, 0, 1),
+            ]
+        );
+
+        drop(capturer);
+        drop(simulator);
+        server.join().unwrap();
+    }
+}
@@ -0,0 +1,292 @@
+use std::mem;
+use std::ops::Deref;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::geometry::Rect;
+use crate::image::{Diff, Screenshot};
+
+use super::Capturer;
+
+/// A change of the watched region, carrying the frame it was seen in.
+#[derive(Debug)]
+pub struct ChangeEvent {
+    time: Instant,
+    screenshot: Screenshot,
+    diff: Diff,
+}
+
+impl ChangeEvent {
+    pub fn time(&self) -> Instant {
+        self.time
+    }
+
+    pub fn screenshot(&self) -> &Screenshot {
+        &self.screenshot
+    }
+
+    pub fn into_screenshot(self) -> Screenshot {
+        self.screenshot
+    }
+
+    /// Number of changed pixels.
+    pub fn changed(&self) -> u32 {
+        self.diff.changed()
+    }
+
+    /// Bounding boxes of the changed regions.
+    pub fn rects(&self) -> &[Rect] {
+        self.diff.rects()
+    }
+}
+
+/// Compares frames with the last reported one, so that slow changes add up
+/// until they pass the threshold.
+pub struct ChangeDetector {
+    region: Option<Rect>,
+    threshold: u32,
+    reference: Option<Screenshot>,
+}
+
+impl ChangeDetector {
+    /// Reports changes of more than `threshold` pixels within `region`, or
+    /// the whole screen if not given.
+    pub fn new(region: Option<Rect>, threshold: u32) -> Self {
+        Self {
+            region,
+            threshold,
+            reference: None,
+        }
+    }
+
+    /// Checks a frame, which becomes the new reference if it changed. The
+    /// first frame only sets the reference.
+    pub fn check(&mut self, screenshot: &Screenshot) -> Option<Diff> {
+        match &mut self.reference {
+            Some(reference) => {
+                let diff = screenshot.diff(reference, self.region);
+                if diff.changed() > self.threshold {
+                    reference.copy_from(screenshot);
+                    Some(diff)
+                } else {
+                    None
+                }
+            }
+            None => {
+                let mut reference = Screenshot::default();
+                reference.copy_from(screenshot);
+                self.reference = Some(reference);
+                None
+            }
+        }
+    }
+
+    // Captures every `interval` until a change, or until `running` turns
+    // false
+    fn poll<F, R>(
+        &mut self,
+        capture: &mut F,
+        screenshot: &mut Screenshot,
+        interval: Duration,
+        running: R,
+    ) -> Option<ChangeEvent>
+    where
+        F: FnMut(&mut Screenshot),
+        R: Fn() -> bool,
+    {
+        while running() {
+            let start = Instant::now();
+            capture(screenshot);
+            if let Some(diff) = self.check(screenshot) {
+                return Some(ChangeEvent {
+                    time: Instant::now(),
+                    screenshot: mem::take(screenshot),
+                    diff,
+                });
+            }
+            if let Some(rest) = interval.checked_sub(start.elapsed()) {
+                thread::sleep(rest);
+            }
+        }
+        None
+    }
+}
+
+/// Endless iterator over the changes of the screen, see `Capturer::watch`.
+pub struct Watch<'a> {
+    capturer: &'a mut Capturer,
+    detector: ChangeDetector,
+    interval: Duration,
+    screenshot: Screenshot,
+}
+
+impl<'a> Iterator for Watch<'a> {
+    type Item = ChangeEvent;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let capturer = &mut *self.capturer;
+        let mut capture = |screenshot: &mut Screenshot| capturer.frame_into(screenshot);
+        self.detector
+            .poll(&mut capture, &mut self.screenshot, self.interval, || true)
+    }
+}
+
+impl Capturer {
+    /// Polls the screen every `interval` and yields an event whenever more
+    /// than `threshold` pixels of `region` changed since the last event.
+    pub fn watch(&mut self, region: Option<Rect>, threshold: u32, interval: Duration) -> Watch<'_> {
+        Watch {
+            capturer: self,
+            detector: ChangeDetector::new(region, threshold),
+            interval,
+            screenshot: Default::default(),
+        }
+    }
+}
+
+/// Receiver of the changes seen by a watching thread, which stops the thread
+/// when dropped.
+pub struct WatchThread {
+    receiver: Receiver<ChangeEvent>,
+    stopped: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Deref for WatchThread {
+    type Target = Receiver<ChangeEvent>;
+
+    fn deref(&self) -> &Receiver<ChangeEvent> {
+        &self.receiver
+    }
+}
+
+impl Drop for WatchThread {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::Release);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// Watches the primary display on a background thread, like
+/// `Capturer::watch`. The thread stops once the returned `WatchThread` is
+/// dropped.
+pub fn spawn_watch(region: Option<Rect>, threshold: u32, interval: Duration) -> WatchThread {
+    spawn_watch_with(region, threshold, interval, || {
+        let mut capturer = Capturer::new();
+        move |screenshot: &mut Screenshot| capturer.frame_into(screenshot)
+    })
+}
+
+/// Like `spawn_watch`, with the capture function created by `init` on the
+/// watching thread.
+pub fn spawn_watch_with<F, G>(
+    region: Option<Rect>,
+    threshold: u32,
+    interval: Duration,
+    init: F,
+) -> WatchThread
+where
+    F: FnOnce() -> G + Send + 'static,
+    G: FnMut(&mut Screenshot),
+{
+    let (sender, receiver) = mpsc::channel();
+    let stopped = Arc::new(AtomicBool::new(false));
+    let handle = {
+        let stopped = stopped.clone();
+        thread::spawn(move || {
+            let mut capture = init();
+            let mut detector = ChangeDetector::new(region, threshold);
+            let mut screenshot = Screenshot::default();
+            let running = || !stopped.load(Ordering::Acquire);
+            while let Some(event) = detector.poll(&mut capture, &mut screenshot, interval, running)
+            {
+                if sender.send(event).is_err() {
+                    break;
+                }
+            }
+        })
+    };
+    WatchThread {
+        receiver,
+        stopped,
+        handle: Some(handle),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image::PixelFormat;
+
+    fn screenshot(lumas: &[u8]) -> Screenshot {
+        Screenshot::from_raw(lumas.len() as u32, 1, lumas.len(), PixelFormat::Gray, lumas).unwrap()
+    }
+
+    #[test]
+    fn detect_changes_beyond_threshold() {
+        let mut detector = ChangeDetector::new(None, 1);
+        assert!(detector.check(&screenshot(&[0, 0, 0, 0])).is_none());
+        assert!(detector.check(&screenshot(&[1, 0, 0, 0])).is_none());
+
+        // Changes add up against the last reported frame
+        let diff = detector.check(&screenshot(&[1, 1, 0, 0])).unwrap();
+        assert_eq!(diff.changed(), 2);
+        assert!(detector.check(&screenshot(&[1, 1, 0, 0])).is_none());
+
+        let mut detector = ChangeDetector::new(Some(Rect::new(2, 0, 2, 1)), 0);
+        detector.check(&screenshot(&[0, 0, 0, 0]));
+        assert!(detector.check(&screenshot(&[9, 9, 0, 0])).is_none());
+        assert_eq!(
+            detector.check(&screenshot(&[9, 9, 0, 9])).unwrap().rects(),
+            &[Rect::new(3, 0, 1, 1)]
+        );
+    }
+
+    #[test]
+    fn receive_changes_from_thread() {
+        let receiver = spawn_watch_with(None, 0, Duration::from_millis(1), || {
+            let mut count = 0u8;
+            move |screenshot: &mut Screenshot| {
+                // Changes on every third frame
+                count = count.wrapping_add(1);
+                screenshot
+                    .fill_from_raw(1, 1, 1, PixelFormat::Gray, &[count / 3])
+                    .unwrap();
+            }
+        });
+
+        let first = receiver.recv().unwrap();
+        let second = receiver.recv().unwrap();
+        assert_eq!(first.rects(), &[Rect::new(0, 0, 1, 1)]);
+        assert_eq!(
+            second.screenshot().pixel(0, 0).luma(),
+            first.screenshot().pixel(0, 0).luma() + 1
+        );
+    }
+
+    #[test]
+    fn stop_watching_static_screen() {
+        let frames = Arc::new(());
+        let receiver = {
+            let frames = frames.clone();
+            spawn_watch_with(None, 0, Duration::from_millis(1), move || {
+                move |screenshot: &mut Screenshot| {
+                    let _ = &frames;
+                    screenshot
+                        .fill_from_raw(1, 1, 1, PixelFormat::Gray, &[0])
+                        .unwrap();
+                }
+            })
+        };
+        assert!(receiver.recv_timeout(Duration::from_millis(20)).is_err());
+
+        // The capture function is dropped along with the stopped thread
+        drop(receiver);
+        assert_eq!(Arc::strong_count(&frames), 1);
+    }
+}
@@ -0,0 +1,265 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::image::Screenshot;
+use crate::Result;
+
+use super::Capturer;
+
+/// A screenshot captured by a `CaptureWorker` and the moment it was taken.
+#[derive(Clone, Debug)]
+pub struct Frame {
+    time: Instant,
+    screenshot: Arc<Screenshot>,
+}
+
+impl Frame {
+    pub fn time(&self) -> Instant {
+        self.time
+    }
+
+    pub fn screenshot(&self) -> &Screenshot {
+        &self.screenshot
+    }
+
+    pub fn into_screenshot(self) -> Arc<Screenshot> {
+        self.screenshot
+    }
+}
+
+struct Shared {
+    frames: Mutex<VecDeque<Frame>>,
+    arrived: Condvar,
+    stopped: AtomicBool,
+    // Set once the capture thread is gone, such as after a capture error
+    dead: AtomicBool,
+}
+
+// Marks the worker dead when the capture thread ends, even by panicking
+struct DeathGuard(Arc<Shared>);
+
+impl Drop for DeathGuard {
+    fn drop(&mut self) {
+        // Set under the lock so that no waiter misses the notification
+        let _frames = self.0.frames.lock().unwrap_or_else(|err| err.into_inner());
+        self.0.dead.store(true, Ordering::Release);
+        self.0.arrived.notify_all();
+    }
+}
+
+/// Captures frames on a background thread at a fixed rate, keeping the most
+/// recent ones in a ring buffer.
+pub struct CaptureWorker {
+    shared: Arc<Shared>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl CaptureWorker {
+    /// Spawns a worker capturing the primary display every `interval`, keeping
+    /// at most `capacity` frames.
+    pub fn spawn(interval: Duration, capacity: usize) -> Self {
+        Self::spawn_with(interval, capacity, || {
+            let mut capturer = Capturer::new();
+            move |screenshot: &mut Screenshot| capturer.frame_into(screenshot)
+        })
+    }
+
+    /// Spawns a worker whose capture function is created by `init` on the
+    /// worker thread, since capturers may not be sent between threads.
+    pub fn spawn_with<F, G>(interval: Duration, capacity: usize, init: F) -> Self
+    where
+        F: FnOnce() -> G + Send + 'static,
+        G: FnMut(&mut Screenshot),
+    {
+        let capacity = capacity.max(1);
+        let shared = Arc::new(Shared {
+            frames: Mutex::new(VecDeque::with_capacity(capacity)),
+            arrived: Condvar::new(),
+            stopped: AtomicBool::new(false),
+            dead: AtomicBool::new(false),
+        });
+
+        let handle = {
+            let shared = shared.clone();
+            thread::spawn(move || {
+                let _guard = DeathGuard(shared.clone());
+                let mut capture = init();
+                let mut spare = Screenshot::default();
+                while !shared.stopped.load(Ordering::Acquire) {
+                    let start = Instant::now();
+
+                    capture(&mut spare);
+                    let frame = Frame {
+                        time: Instant::now(),
+                        screenshot: Arc::new(spare),
+                    };
+
+                    let evicted = {
+                        let mut frames = shared.frames.lock().unwrap();
+                        let evicted = if frames.len() == capacity {
+                            frames.pop_front()
+                        } else {
+                            None
+                        };
+                        frames.push_back(frame);
+                        evicted
+                    };
+                    shared.arrived.notify_all();
+
+                    // Reuse the buffer of the evicted frame unless someone still holds it
+                    spare = evicted
+                        .and_then(|frame| Arc::try_unwrap(frame.screenshot).ok())
+                        .unwrap_or_default();
+
+                    if let Some(rest) = interval.checked_sub(start.elapsed()) {
+                        thread::sleep(rest);
+                    }
+                }
+            })
+        };
+
+        Self {
+            shared,
+            handle: Some(handle),
+        }
+    }
+
+    /// Returns the most recent frame, if any frame has been captured yet.
+    pub fn latest(&self) -> Option<Frame> {
+        self.shared.frames.lock().unwrap().back().cloned()
+    }
+
+    /// Returns the most recent frame, waiting for the first one to be captured.
+    /// Fails once the capture thread has stopped, such as on a capture error.
+    pub fn wait_latest(&self) -> Result<Frame> {
+        let mut frames = self.shared.frames.lock().unwrap();
+        loop {
+            if self.is_dead() {
+                return Err(Self::DEAD.to_string());
+            }
+            if let Some(frame) = frames.back() {
+                return Ok(frame.clone());
+            }
+            frames = self.shared.arrived.wait(frames).unwrap();
+        }
+    }
+
+    /// Waits for the first frame captured after `time`, giving up after
+    /// `timeout` or once the capture thread has stopped.
+    pub fn next_after(&self, time: Instant, timeout: Duration) -> Option<Frame> {
+        let deadline = Instant::now() + timeout;
+        let mut frames = self.shared.frames.lock().unwrap();
+        loop {
+            if let Some(frame) = frames.iter().find(|frame| frame.time > time) {
+                return Some(frame.clone());
+            }
+
+            let now = Instant::now();
+            if now >= deadline || self.is_dead() {
+                return None;
+            }
+            frames = self
+                .shared
+                .arrived
+                .wait_timeout(frames, deadline - now)
+                .unwrap()
+                .0;
+        }
+    }
+
+    /// Whether the capture thread has stopped capturing for good.
+    pub fn is_dead(&self) -> bool {
+        self.shared.dead.load(Ordering::Acquire)
+    }
+
+    const DEAD: &'static str = "The capture worker has stopped";
+
+    /// Returns the buffered frames captured after `time`, oldest first.
+    pub fn frames_since(&self, time: Instant) -> Vec<Frame> {
+        self.shared
+            .frames
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|frame| frame.time > time)
+            .cloned()
+            .collect()
+    }
+}
+
+impl Drop for CaptureWorker {
+    fn drop(&mut self) {
+        self.shared.stopped.store(true, Ordering::Release);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image::PixelFormat;
+
+    fn counting_worker(capacity: usize) -> CaptureWorker {
+        CaptureWorker::spawn_with(Duration::from_millis(2), capacity, || {
+            let mut count = 0u8;
+            move |screenshot: &mut Screenshot| {
+                count = count.wrapping_add(1);
+                screenshot
+                    .fill_from_raw(1, 1, 1, PixelFormat::Gray, &[count])
+                    .unwrap();
+            }
+        })
+    }
+
+    #[test]
+    fn frames_arrive_in_order() {
+        let worker = counting_worker(4);
+        let start = Instant::now();
+
+        let first = worker.next_after(start, Duration::from_secs(5)).unwrap();
+        let second = worker
+            .next_after(first.time(), Duration::from_secs(5))
+            .unwrap();
+        assert!(second.time() > first.time());
+        assert!(second.screenshot().pixel(0, 0).luma() > first.screenshot().pixel(0, 0).luma());
+
+        let latest = worker.latest().unwrap();
+        assert!(latest.time() >= second.time());
+    }
+
+    #[test]
+    fn ring_buffer_is_bounded() {
+        let worker = counting_worker(3);
+        let start = Instant::now();
+        let mut time = start;
+        for _ in 0..6 {
+            time = worker
+                .next_after(time, Duration::from_secs(5))
+                .unwrap()
+                .time();
+        }
+
+        let frames = worker.frames_since(start);
+        assert!(frames.len() <= 3);
+        assert!(frames
+            .windows(2)
+            .all(|pair| pair[0].time() < pair[1].time()));
+    }
+
+    #[test]
+    fn failed_capture_stops_waiting() {
+        let worker = CaptureWorker::spawn_with(Duration::from_millis(2), 3, || {
+            |_: &mut Screenshot| panic!("Connection lost")
+        });
+        assert!(worker.wait_latest().is_err());
+        assert!(worker
+            .next_after(Instant::now(), Duration::from_secs(60))
+            .is_none());
+        assert!(worker.is_dead());
+    }
+}
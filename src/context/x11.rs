@@ -0,0 +1,197 @@
+use std::convert::TryFrom;
+
+use x11rb::connection::{Connection, RequestConnection};
+use x11rb::protocol::xproto::{
+    ConnectionExt as _, ImageFormat, ImageOrder, Window, BUTTON_PRESS_EVENT, BUTTON_RELEASE_EVENT,
+    MOTION_NOTIFY_EVENT,
+};
+use x11rb::protocol::xtest::{self, ConnectionExt as _};
+use x11rb::rust_connection::RustConnection;
+
+use crate::geometry::{ScreenPoint, Size};
+use crate::image::{PixelFormat, Screenshot};
+use crate::Result;
+
+use super::{CaptureBackend, InputBackend, MouseButton};
+
+fn connect(display: Option<&str>) -> Result<(RustConnection, Window, Size)> {
+    let (conn, screen) = x11rb::connect(display).map_err(|err| err.to_string())?;
+    let screen = &conn.setup().roots[screen];
+    let root = screen.root;
+    let size = Size::new(
+        screen.width_in_pixels as u32,
+        screen.height_in_pixels as u32,
+    );
+    Ok((conn, root, size))
+}
+
+/// Captures the root window of an X11 screen.
+///
+/// Only screens of 24 or 32 bits depth, stored in 32 bits per pixel, are
+/// supported, which is what X servers use nowadays.
+pub struct X11Capture {
+    conn: RustConnection,
+    root: Window,
+    size: Size,
+}
+
+impl X11Capture {
+    /// Connects to `display`, or the one of `$DISPLAY` if `None`.
+    pub fn connect(display: Option<&str>) -> Result<Self> {
+        let (conn, root, size) = connect(display)?;
+        Ok(Self { conn, root, size })
+    }
+}
+
+impl CaptureBackend for X11Capture {
+    fn screen_size(&self) -> Size {
+        self.size
+    }
+
+    fn try_frame_into(&mut self, screenshot: &mut Screenshot) -> Result<bool> {
+        let (w, h) = self.size.into();
+        let image = self
+            .conn
+            .get_image(
+                ImageFormat::Z_PIXMAP,
+                self.root,
+                0,
+                0,
+                w as u16,
+                h as u16,
+                !0,
+            )
+            .map_err(|err| err.to_string())?
+            .reply()
+            .map_err(|err| err.to_string())?;
+
+        let setup = self.conn.setup();
+        let bits_per_pixel = setup
+            .pixmap_formats
+            .iter()
+            .find(|format| format.depth == image.depth)
+            .map(|format| format.bits_per_pixel);
+        if bits_per_pixel != Some(32) || setup.image_byte_order != ImageOrder::LSB_FIRST {
+            return Err(format!(
+                "Unsupported X11 image of depth {} in {:?} byte order",
+                image.depth, setup.image_byte_order
+            ));
+        }
+
+        // Rows are padded to the scanline pad of the server
+        let stride = image.data.len() / h as usize;
+        screenshot.fill_from_raw(w, h, stride, PixelFormat::Bgrx, &image.data)?;
+        Ok(true)
+    }
+}
+
+/// Simulates input on an X11 screen through the XTEST extension.
+pub struct X11Input {
+    conn: RustConnection,
+    root: Window,
+}
+
+impl X11Input {
+    // Buttons of the core protocol, where the wheel is reported as buttons
+    const LEFT: u8 = 1;
+    const MIDDLE: u8 = 2;
+    const RIGHT: u8 = 3;
+    const SCROLL_UP: u8 = 4;
+    const SCROLL_DOWN: u8 = 5;
+    const SCROLL_LEFT: u8 = 6;
+    const SCROLL_RIGHT: u8 = 7;
+
+    /// Connects to `display`, or the one of `$DISPLAY` if `None`.
+    pub fn connect(display: Option<&str>) -> Result<Self> {
+        let (conn, root, _) = connect(display)?;
+        if conn
+            .extension_information(xtest::X11_EXTENSION_NAME)
+            .map_err(|err| err.to_string())?
+            .is_none()
+        {
+            return Err("X server does not support the XTEST extension".to_string());
+        }
+        Ok(Self { conn, root })
+    }
+
+    // Waits for the server to process the event, so that it has taken effect
+    // before the next capture
+    fn fake_input(&self, type_: u8, detail: u8, x: i32, y: i32) -> Result<()> {
+        let x = i16::try_from(x).map_err(|_| format!("Coordinate {} is out of range", x))?;
+        let y = i16::try_from(y).map_err(|_| format!("Coordinate {} is out of range", y))?;
+        self.conn
+            .xtest_fake_input(type_, detail, x11rb::CURRENT_TIME, self.root, x, y, 0)
+            .map_err(|err| err.to_string())?
+            .check()
+            .map_err(|err| err.to_string())
+    }
+
+    fn button(btn: MouseButton) -> u8 {
+        match btn {
+            MouseButton::Left => Self::LEFT,
+            MouseButton::Middle => Self::MIDDLE,
+            MouseButton::Right => Self::RIGHT,
+        }
+    }
+
+    fn click(&self, button: u8) -> Result<()> {
+        self.fake_input(BUTTON_PRESS_EVENT, button, 0, 0)?;
+        self.fake_input(BUTTON_RELEASE_EVENT, button, 0, 0)
+    }
+}
+
+impl InputBackend for X11Input {
+    fn cursor_position(&self) -> Result<ScreenPoint> {
+        let pointer = self
+            .conn
+            .query_pointer(self.root)
+            .map_err(|err| err.to_string())?
+            .reply()
+            .map_err(|err| err.to_string())?;
+        Ok(ScreenPoint::new(
+            pointer.root_x as i32,
+            pointer.root_y as i32,
+        ))
+    }
+
+    fn mouse_move_to(&mut self, pos: ScreenPoint) -> Result<()> {
+        self.fake_input(MOTION_NOTIFY_EVENT, 0, pos.x, pos.y)
+    }
+
+    fn mouse_move_by(&mut self, dx: i32, dy: i32) -> Result<()> {
+        // A true detail moves relatively to the current position
+        self.fake_input(MOTION_NOTIFY_EVENT, 1, dx, dy)
+    }
+
+    fn mouse_click(&mut self, btn: MouseButton) -> Result<()> {
+        self.click(Self::button(btn))
+    }
+
+    fn mouse_down(&mut self, btn: MouseButton) -> Result<()> {
+        self.fake_input(BUTTON_PRESS_EVENT, Self::button(btn), 0, 0)
+    }
+
+    fn mouse_up(&mut self, btn: MouseButton) -> Result<()> {
+        self.fake_input(BUTTON_RELEASE_EVENT, Self::button(btn), 0, 0)
+    }
+
+    fn mouse_scroll(&mut self, dx: i32, dy: i32) -> Result<()> {
+        let horizontal = if dx < 0 {
+            Self::SCROLL_LEFT
+        } else {
+            Self::SCROLL_RIGHT
+        };
+        for _ in 0..dx.unsigned_abs() {
+            self.click(horizontal)?;
+        }
+        let vertical = if dy < 0 {
+            Self::SCROLL_UP
+        } else {
+            Self::SCROLL_DOWN
+        };
+        for _ in 0..dy.unsigned_abs() {
+            self.click(vertical)?;
+        }
+        Ok(())
+    }
+}
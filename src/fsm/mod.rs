@@ -92,8 +92,13 @@ pub struct Fsm<C, S: State<C>, T: Transition<C, S>> {
 
     state_id_counter: StateId,
     transition_id_counter: TransitionId,
+
+    on_transition: Option<TransitionHook<C>>,
 }
 
+/// Called with the source and destination states whenever a transition is taken.
+pub type TransitionHook<C> = Box<dyn FnMut(&mut C, StateId, StateId) + Send>;
+
 impl<C, S: State<C>, T: Transition<C, S>> Fsm<C, S, T> {
     pub fn new(entry_state: S, exit_state: S) -> Fsm<C, S, T> {
         let mut fsm = Fsm {
@@ -107,6 +112,7 @@ impl<C, S: State<C>, T: Transition<C, S>> Fsm<C, S, T> {
             curr_state_finished: false,
             state_id_counter: StateId(0),
             transition_id_counter: TransitionId(0),
+            on_transition: None,
         };
         fsm.entry_state_id = fsm.add_state(entry_state);
         fsm.exit_state_id = fsm.add_state(exit_state);
@@ -137,6 +143,10 @@ impl<C, S: State<C>, T: Transition<C, S>> Fsm<C, S, T> {
         self.node_map.get_mut(&self.curr_state_id).unwrap()
     }
 
+    pub fn set_on_transition(&mut self, hook: Option<TransitionHook<C>>) {
+        self.on_transition = hook;
+    }
+
     pub fn add_state(&mut self, state: S) -> StateId {
         self.state_id_counter.0 += 1;
         self.node_map
@@ -208,10 +218,15 @@ impl<C, S: State<C>, T: Transition<C, S>> Fsm<C, S, T> {
                 let edge = &self.edge_map[transition_id];
                 let dst_state = &self.node_map[&edge.dst_id].state;
                 if edge.transition.satisfied(ctx, &curr_node.state, dst_state) {
+                    let prev_state_id = self.curr_state_id;
                     let next_state_id = edge.dst_id;
                     let curr_state = self.curr_state_mut();
                     curr_state.exit(ctx);
 
+                    if let Some(hook) = &mut self.on_transition {
+                        hook(ctx, prev_state_id, next_state_id);
+                    }
+
                     self.curr_state_id = next_state_id;
                     self.curr_state_finished = false;
                     let curr_state = self.curr_state_mut();
@@ -351,6 +366,39 @@ mod tests {
         assert_eq!(fsm.curr_state_id(), exit_id);
     }
 
+    #[test]
+    fn report_transitions() {
+        use std::sync::{Arc, Mutex};
+
+        // The hook keeps the machine movable to another thread
+        fn assert_send<T: Send>(_: &T) {}
+
+        let mut ctx = Context;
+        let mut fsm = Fsm::new(ActionState::Entry, ActionState::Exit);
+        let entry_id = fsm.entry_state_id();
+        let exit_id = fsm.exit_state_id();
+        let nap_id = fsm.add_state(ActionState::Nap { tick_time: 1 });
+        fsm.add_transition(entry_id, nap_id, ActionTransition::Direct);
+        fsm.add_transition(nap_id, exit_id, ActionTransition::NapFinished);
+
+        let transitions = Arc::new(Mutex::new(vec![]));
+        {
+            let transitions = transitions.clone();
+            fsm.set_on_transition(Some(Box::new(move |_, src, dst| {
+                transitions.lock().unwrap().push((src, dst))
+            })));
+        }
+        assert_send(&fsm);
+
+        fsm.tick(&mut ctx);
+        fsm.tick(&mut ctx);
+        fsm.tick(&mut ctx);
+        assert_eq!(
+            *transitions.lock().unwrap(),
+            vec![(entry_id, nap_id), (nap_id, exit_id)]
+        );
+    }
+
     #[test]
     fn eat_fish_and_then_chip() {
         let mut ctx = Context;
@@ -1,62 +1,184 @@
+use std::time::{Duration, Instant};
+
 use super::{State, Transition};
 use crate::context::Context;
 use crate::context::MouseButton;
+use crate::geometry::{Point, Rect};
 use crate::image::Direction;
 use crate::image::Finder;
-use crate::image::Pattern;
+use crate::image::PatternHandle;
+use crate::image::PatternRef;
+use crate::image::RelativeQuery;
+use crate::image::Screenshot;
+
+/// Progress of a preset state watching the screen for changes.
+#[derive(Default)]
+pub struct ScreenWatch {
+    reference: Screenshot,
+    since: Option<Instant>,
+}
+
+impl ScreenWatch {
+    fn reset(&mut self, screenshot: &Screenshot) {
+        self.reference.copy_from(screenshot);
+        self.since = Some(Instant::now());
+    }
+
+    fn elapsed(&self) -> Duration {
+        self.since.map(|since| since.elapsed()).unwrap_or_default()
+    }
+}
 
 pub enum PresetState<'a> {
+    /// Moves the mouse to the hotspot of `pattern`, moved by `offset` in
+    /// pixels of the pattern image. Waits while that's left of or above the
+    /// frame.
     MouseMoveTo {
-        pattern: &'a Pattern,
+        pattern: PatternRef<'a>,
         dir: Direction,
+        offset: (i32, i32),
     },
     MouseClick {
         btn: MouseButton,
     },
+    /// Clicks the hotspot of `pattern`, moved by `offset` like in
+    /// `MouseMoveTo`.
     MouseClickAt {
-        pattern: &'a Pattern,
+        pattern: PatternRef<'a>,
         dir: Direction,
         btn: MouseButton,
+        offset: (i32, i32),
+    },
+    /// Clicks `pattern` like `MouseClickAt`, only where it stands in the
+    /// relations of `query` to its anchor.
+    MouseClickRelative {
+        query: RelativeQuery<'a>,
+        pattern: PatternRef<'a>,
+        dir: Direction,
+        btn: MouseButton,
+        offset: (i32, i32),
     },
     MouseScroll {
         dx: i32,
         dy: i32,
     },
+    WaitUntilStable {
+        region: Option<Rect>,
+        duration: Duration,
+        watch: ScreenWatch,
+    },
+    WaitUntilChanged {
+        region: Option<Rect>,
+        watch: ScreenWatch,
+    },
     Emtpy,
     Entry,
     Exit,
 }
 
+impl<'a> PresetState<'a> {
+    /// Finishes once the screen, or `region` of it, has not changed for `duration`.
+    /// The region is at the reference resolution of the context, if one is set.
+    pub fn wait_until_stable(region: Option<Rect>, duration: Duration) -> Self {
+        PresetState::WaitUntilStable {
+            region,
+            duration,
+            watch: Default::default(),
+        }
+    }
+
+    /// Finishes once the screen, or `region` of it, differs from the moment
+    /// the state was entered. The region is mapped like in `wait_until_stable`.
+    pub fn wait_until_changed(region: Option<Rect>) -> Self {
+        PresetState::WaitUntilChanged {
+            region,
+            watch: Default::default(),
+        }
+    }
+}
+
 impl<'a> State<Context> for PresetState<'a> {
     fn enter(&mut self, ctx: &mut Context) {
         match self {
             PresetState::MouseClick { btn } => ctx.simulator_mut().mouse_click(*btn),
             PresetState::MouseScroll { dx, dy } => ctx.simulator_mut().mouse_scroll(*dx, *dy),
+            PresetState::WaitUntilStable { watch, .. }
+            | PresetState::WaitUntilChanged { watch, .. } => watch.reset(ctx.frame()),
             _ => {}
         }
     }
 
     fn tick(&mut self, ctx: &mut Context) -> bool {
         match self {
-            PresetState::MouseMoveTo { pattern, dir } => {
-                let screenshot = ctx.capturer_mut().frame();
-                let finder = Finder::new(&screenshot);
-                if let Some(pos) = finder.find(pattern, *dir) {
-                    ctx.simulator_mut().mouse_move_to(pos.0, pos.1);
+            PresetState::MouseMoveTo {
+                pattern,
+                dir,
+                offset,
+            } => {
+                ctx.poll_hot_reload();
+                let pattern = match pattern.resolve(ctx.pattern_library()) {
+                    Some(pattern) => pattern,
+                    None => return false,
+                };
+                let pos = Finder::new(ctx.frame()).find(&pattern, *dir);
+                if let Some(target) = pos.and_then(|pos| pattern.target(pos, *offset)) {
+                    ctx.mouse_move_to_capture(target);
+                    return true;
+                }
+                false
+            }
+            PresetState::MouseClickAt {
+                pattern,
+                dir,
+                btn,
+                offset,
+            } => {
+                ctx.poll_hot_reload();
+                let pattern = match pattern.resolve(ctx.pattern_library()) {
+                    Some(pattern) => pattern,
+                    None => return false,
+                };
+                let pos = Finder::new(ctx.frame()).find(&pattern, *dir);
+                if let Some(target) = pos.and_then(|pos| pattern.target(pos, *offset)) {
+                    ctx.mouse_move_to_capture(target);
+                    ctx.simulator_mut().mouse_click(*btn);
                     return true;
                 }
                 false
             }
-            PresetState::MouseClickAt { pattern, dir, btn } => {
-                let screenshot = ctx.capturer_mut().frame();
-                let finder = Finder::new(&screenshot);
-                if let Some(pos) = finder.find(pattern, *dir) {
-                    ctx.simulator_mut().mouse_move_to(pos.0, pos.1);
+            PresetState::MouseClickRelative {
+                query,
+                pattern,
+                dir,
+                btn,
+                offset,
+            } => {
+                let target = find_relative(ctx, query, pattern, *dir)
+                    .and_then(|(pattern, pos)| pattern.target(pos, *offset));
+                if let Some(target) = target {
+                    ctx.mouse_move_to_capture(target);
                     ctx.simulator_mut().mouse_click(*btn);
                     return true;
                 }
                 false
             }
+            PresetState::WaitUntilStable {
+                region,
+                duration,
+                watch,
+            } => {
+                let region = region.map(|region| ctx.map_rect(region));
+                let screenshot = ctx.frame();
+                if !screenshot.diff(&watch.reference, region).is_empty() {
+                    watch.reset(screenshot);
+                    return false;
+                }
+                watch.elapsed() >= *duration
+            }
+            PresetState::WaitUntilChanged { region, watch } => {
+                let region = region.map(|region| ctx.map_rect(region));
+                !ctx.frame().diff(&watch.reference, region).is_empty()
+            }
             _ => true,
         }
     }
@@ -64,9 +186,32 @@ impl<'a> State<Context> for PresetState<'a> {
     fn exit(&mut self, _ctx: &mut Context) {}
 }
 
+// Finds `pattern` relative to the anchor of `query` in a new frame, resolving
+// both before capturing it
+fn find_relative<'a>(
+    ctx: &mut Context,
+    query: &RelativeQuery<'a>,
+    pattern: &PatternRef<'a>,
+    dir: Direction,
+) -> Option<(PatternHandle<'a>, Point)> {
+    ctx.poll_hot_reload();
+    let library = ctx.pattern_library();
+    let anchor = query.anchor().resolve(library)?;
+    let pattern = pattern.resolve(library)?;
+    let (_, pos) = query.find_from(&Finder::new(ctx.frame()), &anchor, &pattern, dir)?;
+    Some((pattern, pos))
+}
+
 pub enum PresetTransition<'a> {
     PatternFound {
-        pattern: &'a Pattern,
+        pattern: PatternRef<'a>,
+        dir: Direction,
+    },
+    /// Satisfied once `pattern` is found standing in the relations of
+    /// `query` to its anchor.
+    RelativeFound {
+        query: RelativeQuery<'a>,
+        pattern: PatternRef<'a>,
         dir: Direction,
     },
     Direct,
@@ -76,10 +221,17 @@ impl<'a> Transition<Context, PresetState<'a>> for PresetTransition<'a> {
     fn satisfied(&self, ctx: &mut Context, _src: &PresetState, _dst: &PresetState) -> bool {
         match self {
             PresetTransition::PatternFound { pattern, dir } => {
-                let screenshot = ctx.capturer_mut().frame();
-                let finder = Finder::new(&screenshot);
-                finder.find(pattern, *dir).is_some()
+                ctx.poll_hot_reload();
+                match pattern.resolve(ctx.pattern_library()) {
+                    Some(pattern) => Finder::new(ctx.frame()).find(&pattern, *dir).is_some(),
+                    None => false,
+                }
             }
+            PresetTransition::RelativeFound {
+                query,
+                pattern,
+                dir,
+            } => find_relative(ctx, query, pattern, *dir).is_some(),
             PresetTransition::Direct => true,
         }
     }
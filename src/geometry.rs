@@ -0,0 +1,312 @@
+//! Positions, sizes and rectangles in pixels of the screen.
+
+use std::ops::{Add, Sub};
+
+/// A position in captured frames, with `y` growing downwards. Positions on
+/// the desktop, which may be negative, are `ScreenPoint`s.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Point {
+    pub x: u32,
+    pub y: u32,
+}
+
+impl Point {
+    pub fn new(x: u32, y: u32) -> Self {
+        Self { x, y }
+    }
+
+    /// Moves by `dx` and `dy`, stopping at zero.
+    pub fn offset(&self, dx: i32, dy: i32) -> Point {
+        Point::new(
+            (self.x as i64 + dx as i64).clamp(0, u32::MAX as i64) as u32,
+            (self.y as i64 + dy as i64).clamp(0, u32::MAX as i64) as u32,
+        )
+    }
+
+    /// Scales both coordinates, rounding to the nearest pixel.
+    pub fn scale(&self, scale_x: f64, scale_y: f64) -> Point {
+        Point::new(
+            (self.x as f64 * scale_x).round() as u32,
+            (self.y as f64 * scale_y).round() as u32,
+        )
+    }
+
+    /// Distance to another point.
+    pub fn distance(&self, other: Point) -> f64 {
+        let dx = self.x as f64 - other.x as f64;
+        let dy = self.y as f64 - other.y as f64;
+        dx.hypot(dy)
+    }
+}
+
+impl From<(u32, u32)> for Point {
+    fn from((x, y): (u32, u32)) -> Self {
+        Point::new(x, y)
+    }
+}
+
+impl From<Point> for (u32, u32) {
+    fn from(point: Point) -> Self {
+        (point.x, point.y)
+    }
+}
+
+impl Add<Size> for Point {
+    type Output = Point;
+
+    fn add(self, size: Size) -> Point {
+        Point::new(self.x + size.width, self.y + size.height)
+    }
+}
+
+/// Gives the size from `other` to this point.
+impl Sub for Point {
+    type Output = Size;
+
+    fn sub(self, other: Point) -> Size {
+        Size::new(self.x - other.x, self.y - other.y)
+    }
+}
+
+/// A position on the screens of the desktop, as taken by the input
+/// backends. Screens left of or above the primary one have negative
+/// coordinates.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct ScreenPoint {
+    pub x: i32,
+    pub y: i32,
+}
+
+impl ScreenPoint {
+    pub fn new(x: i32, y: i32) -> Self {
+        Self { x, y }
+    }
+
+    /// Moves by `dx` and `dy`.
+    pub fn offset(&self, dx: i32, dy: i32) -> ScreenPoint {
+        ScreenPoint::new(self.x.saturating_add(dx), self.y.saturating_add(dy))
+    }
+}
+
+impl From<(i32, i32)> for ScreenPoint {
+    fn from((x, y): (i32, i32)) -> Self {
+        ScreenPoint::new(x, y)
+    }
+}
+
+impl From<ScreenPoint> for (i32, i32) {
+    fn from(point: ScreenPoint) -> Self {
+        (point.x, point.y)
+    }
+}
+
+impl From<Point> for ScreenPoint {
+    fn from(point: Point) -> Self {
+        let clamp = |value: u32| value.min(i32::MAX as u32) as i32;
+        ScreenPoint::new(clamp(point.x), clamp(point.y))
+    }
+}
+
+/// A width and height.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Size {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Size {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self { width, height }
+    }
+
+    pub fn area(&self) -> u64 {
+        self.width as u64 * self.height as u64
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+
+    /// Scales both dimensions, rounding to the nearest pixel.
+    pub fn scale(&self, scale_x: f64, scale_y: f64) -> Size {
+        Size::new(
+            (self.width as f64 * scale_x).round() as u32,
+            (self.height as f64 * scale_y).round() as u32,
+        )
+    }
+}
+
+impl From<(u32, u32)> for Size {
+    fn from((width, height): (u32, u32)) -> Self {
+        Size::new(width, height)
+    }
+}
+
+impl From<Size> for (u32, u32) {
+    fn from(size: Size) -> Self {
+        (size.width, size.height)
+    }
+}
+
+/// A point of a rectangle, by its horizontal and vertical alignment.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Anchor {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+/// An axis-aligned rectangle.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Rect {
+    pub fn new(x: u32, y: u32, width: u32, height: u32) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    pub fn from_origin_size(origin: Point, size: Size) -> Self {
+        Self::new(origin.x, origin.y, size.width, size.height)
+    }
+
+    /// Returns the rectangle of `size` centered on `center`, as far as it
+    /// stays at non-negative coordinates.
+    pub fn centered_at(center: Point, size: Size) -> Self {
+        let origin = Point::new(
+            center.x.saturating_sub(size.width / 2),
+            center.y.saturating_sub(size.height / 2),
+        );
+        Self::from_origin_size(origin, size)
+    }
+
+    pub fn origin(&self) -> Point {
+        Point::new(self.x, self.y)
+    }
+
+    pub fn size(&self) -> Size {
+        Size::new(self.width, self.height)
+    }
+
+    /// Horizontal coordinate past the right edge.
+    pub fn right(&self) -> u32 {
+        self.x + self.width
+    }
+
+    /// Vertical coordinate past the bottom edge.
+    pub fn bottom(&self) -> u32 {
+        self.y + self.height
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.size().is_empty()
+    }
+
+    pub fn contains(&self, point: Point) -> bool {
+        (self.x..self.right()).contains(&point.x) && (self.y..self.bottom()).contains(&point.y)
+    }
+
+    pub fn contains_rect(&self, other: &Rect) -> bool {
+        self.x <= other.x
+            && self.y <= other.y
+            && other.right() <= self.right()
+            && other.bottom() <= self.bottom()
+    }
+
+    /// Returns the overlapping part of two rectangles, if any.
+    pub fn intersect(&self, other: &Rect) -> Option<Rect> {
+        let x = self.x.max(other.x);
+        let y = self.y.max(other.y);
+        let xx = self.right().min(other.right());
+        let yy = self.bottom().min(other.bottom());
+        if x < xx && y < yy {
+            Some(Rect::new(x, y, xx - x, yy - y))
+        } else {
+            None
+        }
+    }
+
+    /// Returns the smallest rectangle containing both rectangles.
+    pub fn union(&self, other: &Rect) -> Rect {
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        let xx = self.right().max(other.right());
+        let yy = self.bottom().max(other.bottom());
+        Rect::new(x, y, xx - x, yy - y)
+    }
+
+    /// Moves by `dx` and `dy`, stopping at zero.
+    pub fn offset(&self, dx: i32, dy: i32) -> Rect {
+        Rect::from_origin_size(self.origin().offset(dx, dy), self.size())
+    }
+
+    /// Returns the point of the rectangle at `anchor`, where the right and
+    /// bottom ones are the last pixels inside.
+    pub fn anchor(&self, anchor: Anchor) -> Point {
+        let left = self.x;
+        let center = self.x + self.width / 2;
+        let right = self.right().saturating_sub(1).max(self.x);
+        let top = self.y;
+        let middle = self.y + self.height / 2;
+        let bottom = self.bottom().saturating_sub(1).max(self.y);
+        match anchor {
+            Anchor::TopLeft => Point::new(left, top),
+            Anchor::Top => Point::new(center, top),
+            Anchor::TopRight => Point::new(right, top),
+            Anchor::Left => Point::new(left, middle),
+            Anchor::Center => Point::new(center, middle),
+            Anchor::Right => Point::new(right, middle),
+            Anchor::BottomLeft => Point::new(left, bottom),
+            Anchor::Bottom => Point::new(center, bottom),
+            Anchor::BottomRight => Point::new(right, bottom),
+        }
+    }
+
+    pub fn center(&self) -> Point {
+        self.anchor(Anchor::Center)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rect_operations() {
+        let rect = Rect::new(10, 20, 30, 40);
+        assert_eq!(rect.center(), Point::new(25, 40));
+        assert_eq!(rect.anchor(Anchor::BottomRight), Point::new(39, 59));
+        assert!(rect.contains(Point::new(39, 59)));
+        assert!(!rect.contains(Point::new(40, 59)));
+        assert!(rect.contains_rect(&Rect::new(10, 20, 30, 1)));
+
+        assert_eq!(
+            rect.intersect(&Rect::new(30, 50, 100, 100)),
+            Some(Rect::new(30, 50, 10, 10))
+        );
+        assert_eq!(rect.intersect(&Rect::new(40, 20, 1, 1)), None);
+        assert_eq!(rect.union(&Rect::new(0, 0, 1, 1)), Rect::new(0, 0, 40, 60));
+
+        assert_eq!(rect.offset(-15, 5), Rect::new(0, 25, 30, 40));
+        assert_eq!(
+            Rect::centered_at(Point::new(25, 40), Size::new(30, 40)),
+            rect
+        );
+        assert_eq!(Point::new(40, 60) - rect.origin(), Size::new(30, 40));
+    }
+}
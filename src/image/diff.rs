@@ -0,0 +1,142 @@
+use super::Screenshot;
+use crate::geometry::Rect;
+
+/// Pixels that differ between two screenshots.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Diff {
+    changed: u32,
+    rects: Vec<Rect>,
+}
+
+impl Diff {
+    // Changed pixels are grouped by cells of this size, and touching cells are
+    // merged into one rectangle
+    const CELL: u32 = 8;
+
+    pub(super) fn between(a: &Screenshot, b: &Screenshot, region: Option<Rect>) -> Self {
+        let full = Rect::from_origin_size(Default::default(), a.size());
+        let region = match region {
+            Some(region) => match region.intersect(&full) {
+                Some(region) => region,
+                None => return Self::default(),
+            },
+            None => full,
+        };
+        if region.is_empty() {
+            return Self::default();
+        }
+
+        // Screenshots of different sizes are entirely different
+        if a.size() != b.size() {
+            return Self {
+                changed: region.width * region.height,
+                rects: vec![region],
+            };
+        }
+
+        // Tight bounding box of the changed pixels in each cell
+        let cols = region.width.div_ceil(Self::CELL);
+        let rows = region.height.div_ceil(Self::CELL);
+        let mut cells: Vec<Option<Rect>> = vec![None; (cols * rows) as usize];
+
+        let mut changed = 0;
+        let row_len = a.width() as usize * 4;
+        for y in region.y..region.y + region.height {
+            let head = y as usize * row_len;
+            let a_row = &a.bgra_buf()[head..head + row_len];
+            let b_row = &b.bgra_buf()[head..head + row_len];
+            for x in region.x..region.x + region.width {
+                let i = x as usize * 4;
+                // Alpha is ignored as it is not visible
+                if a_row[i..i + 3] == b_row[i..i + 3] {
+                    continue;
+                }
+
+                changed += 1;
+                let cell =
+                    ((y - region.y) / Self::CELL * cols + (x - region.x) / Self::CELL) as usize;
+                let pixel = Rect::new(x, y, 1, 1);
+                cells[cell] = Some(match cells[cell] {
+                    Some(rect) => rect.union(&pixel),
+                    None => pixel,
+                });
+            }
+        }
+
+        // Merge the cells connected in 8 directions
+        let mut rects = vec![];
+        let mut visited = vec![false; cells.len()];
+        let mut stack = vec![];
+        for start in 0..cells.len() {
+            if visited[start] || cells[start].is_none() {
+                continue;
+            }
+            visited[start] = true;
+            stack.push(start);
+            let mut rect = cells[start].unwrap();
+            while let Some(cell) = stack.pop() {
+                rect = rect.union(&cells[cell].unwrap());
+                let (col, row) = ((cell as u32) % cols, (cell as u32) / cols);
+                for row in row.saturating_sub(1)..(row + 2).min(rows) {
+                    for col in col.saturating_sub(1)..(col + 2).min(cols) {
+                        let neighbor = (row * cols + col) as usize;
+                        if !visited[neighbor] && cells[neighbor].is_some() {
+                            visited[neighbor] = true;
+                            stack.push(neighbor);
+                        }
+                    }
+                }
+            }
+            rects.push(rect);
+        }
+
+        Self { changed, rects }
+    }
+
+    /// Number of changed pixels.
+    pub fn changed(&self) -> u32 {
+        self.changed
+    }
+
+    /// Bounding boxes of the changed regions.
+    pub fn rects(&self) -> &[Rect] {
+        &self.rects
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changed == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::PixelFormat;
+    use super::*;
+
+    fn screenshot(width: u32, height: u32, changed: &[(u32, u32)]) -> Screenshot {
+        let mut buf = vec![0u8; (width * height) as usize];
+        for &(x, y) in changed {
+            buf[(y * width + x) as usize] = 255;
+        }
+        Screenshot::from_raw(width, height, width as usize, PixelFormat::Gray, &buf).unwrap()
+    }
+
+    #[test]
+    fn group_changed_pixels() {
+        let a = screenshot(64, 64, &[]);
+        let b = screenshot(64, 64, &[(1, 1), (9, 3), (40, 50), (41, 52)]);
+
+        let diff = a.diff(&b, None);
+        assert_eq!(diff.changed(), 4);
+        assert_eq!(
+            diff.rects(),
+            &[Rect::new(1, 1, 9, 3), Rect::new(40, 50, 2, 3)]
+        );
+
+        let diff = a.diff(&b, Some(Rect::new(32, 32, 32, 32)));
+        assert_eq!(diff.changed(), 2);
+        assert_eq!(diff.rects(), &[Rect::new(40, 50, 2, 3)]);
+
+        assert!(a.diff(&a, None).is_empty());
+    }
+}
@@ -1,4 +1,6 @@
-use super::{FlattenArray, GrayImage, Pattern, Screenshot};
+use crate::geometry::Point;
+
+use super::{kernel, FlattenArray, GrayImage, Pattern, Screenshot};
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Direction {
@@ -9,24 +11,28 @@ pub enum Direction {
 }
 
 impl Direction {
-    pub fn meet(&self, from: (u32, u32), to: (u32, u32)) -> bool {
+    pub fn meet(&self, from: Point, to: Point) -> bool {
         match self {
-            Direction::Up => to.1 < from.1,
-            Direction::Down => to.1 > from.1,
-            Direction::Left => to.0 < from.0,
-            Direction::Right => to.0 > from.0,
+            Direction::Up => to.y < from.y,
+            Direction::Down => to.y > from.y,
+            Direction::Left => to.x < from.x,
+            Direction::Right => to.x > from.x,
         }
     }
 }
 
 struct LumaMatrix {
-    width: u32,
-    height: u32,
+    sums: FlattenArray<u64>,
     square_sums: FlattenArray<u64>,
 }
 
 impl LumaMatrix {
     fn new(image: &GrayImage) -> LumaMatrix {
+        let mut sums = FlattenArray::new(
+            1 + image.width() as usize,
+            1 + image.height() as usize,
+            0u64,
+        );
         let mut square_sums = FlattenArray::new(
             1 + image.width() as usize,
             1 + image.height() as usize,
@@ -38,17 +44,20 @@ impl LumaMatrix {
         for y in 0..height as usize {
             for x in 0..width as usize {
                 let luma = image.pixel(x as u32, y as u32) as u64;
+                sums[(y + 1, x + 1)] = sums[(y, x + 1)] - sums[(y, x)] + sums[(y + 1, x)] + luma;
                 square_sums[(y + 1, x + 1)] = square_sums[(y, x + 1)] - square_sums[(y, x)]
                     + square_sums[(y + 1, x)]
                     + luma * luma;
             }
         }
 
-        LumaMatrix {
-            width,
-            height,
-            square_sums,
-        }
+        LumaMatrix { sums, square_sums }
+    }
+
+    #[inline]
+    fn sum_partial(&self, [y, x, yy, xx]: [u32; 4]) -> u64 {
+        let (y, x, yy, xx) = (y as usize, x as usize, yy as usize, xx as usize);
+        self.sums[(yy, xx)] - self.sums[(yy, x)] + self.sums[(y, x)] - self.sums[(y, xx)]
     }
 
     #[inline]
@@ -68,66 +77,303 @@ impl<'a> Finder<'a> {
         Self { screenshot }
     }
 
-    pub fn find(&self, pattern: &Pattern, dir: Direction) -> Option<(u32, u32)> {
-        const THRESHOLD: f32 = 0.99;
-        const EPS: f32 = 0.005;
+    pub fn find(&self, pattern: &Pattern, dir: Direction) -> Option<Point> {
+        self.find_where(pattern, dir, |_| true)
+    }
 
-        let image = GrayImage::from_screenshot(self.screenshot).into_compressed(pattern.factor());
-        let packed_image = image.to_redundant_packed();
-        let matrix = LumaMatrix::new(&image);
+    /// Like `find`, only considering the matches whose center is accepted by
+    /// `accept`.
+    pub fn find_where<F>(&self, pattern: &Pattern, dir: Direction, accept: F) -> Option<Point>
+    where
+        F: Fn(Point) -> bool,
+    {
+        const EPS: f32 = 0.005;
 
         let mut max_score = 0f32;
         let mut result = None;
+        self.scan(pattern, accept, |center, score| {
+            if (score - max_score).abs() <= EPS {
+                if let Some(curr_res) = result {
+                    if dir.meet(curr_res, center) {
+                        max_score = score;
+                        result = Some(center);
+                    }
+                }
+            } else if score > max_score {
+                max_score = score;
+                result = Some(center);
+            }
+        });
+        result
+    }
+
+    /// Finds every match of `pattern` in reading order. Of matches closer
+    /// than half the pattern size to each other, only the best one is kept.
+    pub fn find_all(&self, pattern: &Pattern) -> Vec<Point> {
+        let mut matches = vec![];
+        self.scan(
+            pattern,
+            |_| true,
+            |center, score| matches.push((score, center)),
+        );
+        matches.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+        let size = pattern.size();
+        let mut result: Vec<Point> = vec![];
+        for (_, center) in matches {
+            let overlaps = result.iter().any(|kept| {
+                kept.x.abs_diff(center.x) < size.width.div_ceil(2)
+                    && kept.y.abs_diff(center.y) < size.height.div_ceil(2)
+            });
+            if !overlaps {
+                result.push(center);
+            }
+        }
+        result.sort_by_key(|center| (center.y, center.x));
+        result
+    }
 
-        for y in 0..matrix.height - pattern.height() + 1 {
-            for x in 0..matrix.width - pattern.width() + 1 {
-                let (y, x) = (y as u32, x as u32);
+    // Calls `on_match` with the center and score of every window accepted by
+    // `accept` and in the region of the pattern that scores above its
+    // threshold
+    fn scan<A, M>(&self, pattern: &Pattern, accept: A, mut on_match: M)
+    where
+        A: Fn(Point) -> bool,
+        M: FnMut(Point, f32),
+    {
+        let threshold = pattern.threshold();
+        let region = pattern.region();
+        let accept = |center| {
+            let in_region = match region {
+                Some(region) => region.contains(center),
+                None => true,
+            };
+            in_region && accept(center)
+        };
 
-                const PACK: usize = 8;
+        let image = GrayImage::from_screenshot_compressed(self.screenshot, pattern.factor());
+        if let Some(mask) = pattern.mask() {
+            return Self::scan_masked(&image, pattern, mask, accept, on_match);
+        }
+        let matrix = LumaMatrix::new(&image);
+        let image = image.into_padded();
 
-                let mut score = 0u32;
-                for dy in 0..pattern.height() {
-                    for dx in (0..pattern.width()).step_by(PACK) {
-                        let image_values = packed_image.pixels(x + dx, y + dy);
-                        let pattern_values = pattern.packed_pixels(dx, dy);
+        let (width, height) = (pattern.width(), pattern.height());
+        let pattern_angle =
+            Self::angle_to_diagonal(pattern.sum(), pattern.square_sum(), (width * height) as u64);
 
-                        let products = *image_values * *pattern_values;
+        for y in 0..image.height() - height + 1 {
+            for x in 0..image.width() - width + 1 {
+                let center = Point::new(
+                    (x + (width >> 1)) * pattern.factor(),
+                    (y + (height >> 1)) * pattern.factor(),
+                );
+                if !accept(center) {
+                    continue;
+                }
 
-                        let arr: [u16; 8] = products.into();
-                        for v in arr {
-                            score += v as u32;
-                        }
-                    }
+                let window_square_sum = matrix.square_sum_partial([y, x, y + height, x + width]);
+
+                // The score of a black window is NaN, which never passes the threshold
+                if window_square_sum == 0 {
+                    continue;
                 }
 
-                let norm =
-                    ((matrix.square_sum_partial([y, x, y + pattern.height(), x + pattern.width()])
-                        * pattern.square_sum()) as f32)
-                        .sqrt();
+                let norm = ((window_square_sum as f64) * (pattern.square_sum() as f64)).sqrt();
+
+                // The angle between the window and the pattern is at least the
+                // difference of their angles to the diagonal vector (1, 1, ..., 1),
+                // which only takes the sums to compute
+                let window_angle = Self::angle_to_diagonal(
+                    matrix.sum_partial([y, x, y + height, x + width]),
+                    window_square_sum,
+                    (width * height) as u64,
+                );
+                if ((window_angle - pattern_angle).abs().cos() as f32) < threshold - Self::MARGIN {
+                    continue;
+                }
+
+                let score = match kernel::window_dot(&image, pattern.packed(), x, y, |rest, dot| {
+                    // By Cauchy-Schwarz the remaining rows can not contribute
+                    // more than the product of their norms
+                    let rest_window_square_sum =
+                        matrix.square_sum_partial([y + rest, x, y + height, x + width]);
+                    let bound = dot as f64
+                        + ((rest_window_square_sum as f64)
+                            * (pattern.rest_square_sum(rest) as f64))
+                            .sqrt();
+                    ((bound / norm) as f32) >= threshold - Self::MARGIN
+                }) {
+                    Some(score) => score,
+                    None => continue,
+                };
+
+                let norm = ((window_square_sum * pattern.square_sum()) as f32).sqrt();
 
                 let score = score as f32 / norm;
 
-                if score >= THRESHOLD {
-                    let center = (
-                        (x + (pattern.width() >> 1)) * pattern.factor(),
-                        (y + (pattern.height() >> 1)) * pattern.factor(),
-                    );
+                if score >= threshold {
+                    on_match(center, score);
+                }
+            }
+        }
+    }
 
-                    if (score - max_score).abs() <= EPS {
-                        if let Some(curr_res) = result {
-                            if dir.meet(curr_res, center) {
-                                max_score = score;
-                                result = Some(center);
-                            }
-                        }
-                    } else if score > max_score {
-                        max_score = score;
-                        result = Some(center);
+    // Like `scan` for a masked pattern, whose correlation only covers the
+    // compared pixels, so that the bounds of the sums do not apply
+    fn scan_masked<A, M>(
+        image: &GrayImage,
+        pattern: &Pattern,
+        mask: &[bool],
+        accept: A,
+        mut on_match: M,
+    ) where
+        A: Fn(Point) -> bool,
+        M: FnMut(Point, f32),
+    {
+        let (width, height) = (pattern.width(), pattern.height());
+        let compared: Vec<(u32, u32)> = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .zip(mask)
+            .filter(|(_, &kept)| kept)
+            .map(|(pos, _)| pos)
+            .collect();
+
+        for y in 0..image.height() - height + 1 {
+            for x in 0..image.width() - width + 1 {
+                let center = Point::new(
+                    (x + (width >> 1)) * pattern.factor(),
+                    (y + (height >> 1)) * pattern.factor(),
+                );
+                if !accept(center) {
+                    continue;
+                }
+
+                let (mut dot, mut window_square_sum) = (0u64, 0u64);
+                for &(dx, dy) in &compared {
+                    let luma = image.pixel(x + dx, y + dy) as u64;
+                    dot += luma * pattern.pixel(dx, dy) as u64;
+                    window_square_sum += luma * luma;
+                }
+                let norm = ((window_square_sum as f64) * (pattern.square_sum() as f64)).sqrt();
+                let score = (dot as f64 / norm) as f32;
+                if score >= pattern.threshold() {
+                    on_match(center, score);
+                }
+            }
+        }
+    }
+
+    // Slack for the rejection bounds so that rounding never rejects a
+    // position the exact score would accept
+    const MARGIN: f32 = 1e-3;
+
+    #[inline]
+    fn angle_to_diagonal(sum: u64, square_sum: u64, len: u64) -> f64 {
+        let cos = sum as f64 / ((len as f64) * (square_sum as f64)).sqrt();
+        cos.min(1.0).acos()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::PixelFormat;
+    use super::*;
+
+    struct Lcg(u64);
+
+    impl Lcg {
+        fn next(&mut self, m: u32) -> u32 {
+            self.0 = self
+                .0
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            ((self.0 >> 33) % m as u64) as u32
+        }
+    }
+
+    // Scores every window like `scan` does, without any rejection bound
+    fn naive_scan(screenshot: &Screenshot, pattern: &Pattern) -> Vec<(Point, f32)> {
+        let image = GrayImage::from_screenshot_compressed(screenshot, pattern.factor());
+        let (width, height) = (pattern.width(), pattern.height());
+        let mut matches = vec![];
+        for y in 0..image.height() - height + 1 {
+            for x in 0..image.width() - width + 1 {
+                let (mut dot, mut window_square_sum) = (0u64, 0u64);
+                for dy in 0..height {
+                    for dx in 0..width {
+                        let luma = image.pixel(x + dx, y + dy) as u64;
+                        dot += luma * pattern.pixel(dx, dy) as u64;
+                        window_square_sum += luma * luma;
                     }
                 }
+                if window_square_sum == 0 {
+                    continue;
+                }
+                let norm = ((window_square_sum * pattern.square_sum()) as f32).sqrt();
+                let score = dot as f32 / norm;
+                if score >= pattern.threshold() {
+                    let center = Point::new(
+                        (x + (width >> 1)) * pattern.factor(),
+                        (y + (height >> 1)) * pattern.factor(),
+                    );
+                    matches.push((center, score));
+                }
             }
         }
+        matches
+    }
 
-        result
+    #[test]
+    fn bounds_never_reject_a_match() {
+        let mut rng = Lcg(7);
+        let (width, height) = (160u32, 120u32);
+        for _ in 0..4 {
+            // Smooth blobs with some noise, so that many windows score high
+            let coarse: Vec<u32> = (0..11 * 9).map(|_| rng.next(256)).collect();
+            let lumas: Vec<u8> = (0..width * height)
+                .map(|i| {
+                    let (x, y) = (i % width / 16, i / width / 16);
+                    (coarse[(y * 11 + x) as usize] * 3 / 4 + rng.next(64)) as u8
+                })
+                .collect();
+            let screenshot =
+                Screenshot::from_raw(width, height, width as usize, PixelFormat::Gray, &lumas)
+                    .unwrap();
+            let (x0, y0) = (rng.next(width - 40), rng.next(height - 20));
+            let item: Vec<u8> = (0..20 * 40)
+                .map(|i| {
+                    let luma = lumas[((y0 + i / 40) * width + x0 + i % 40) as usize];
+                    luma.saturating_add(rng.next(16) as u8)
+                })
+                .collect();
+            let mut pattern = Pattern::from_luma_buf(40, 20, &item).unwrap();
+
+            pattern.set_threshold(0.0);
+            let mut scores: Vec<f32> = naive_scan(&screenshot, &pattern)
+                .into_iter()
+                .map(|(_, score)| score)
+                .collect();
+            scores.sort_by(|a, b| b.total_cmp(a));
+
+            let finder = Finder::new(&screenshot);
+            for &k in &[0, 1, 10, 100, 1000] {
+                // Thresholds right at a score and just above it
+                let score = scores[k];
+                for &threshold in &[score, f32::from_bits(score.to_bits() + 1)] {
+                    pattern.set_threshold(threshold);
+                    let mut found = vec![];
+                    finder.scan(
+                        &pattern,
+                        |_| true,
+                        |center, score| found.push((center, score)),
+                    );
+                    assert_eq!(found, naive_scan(&screenshot, &pattern));
+                }
+            }
+            pattern.set_threshold(scores[0]);
+            let best = naive_scan(&screenshot, &pattern)[0].0;
+            assert_eq!(finder.find(&pattern, Direction::Down), Some(best));
+        }
     }
 }
@@ -23,6 +23,11 @@ impl<T: Copy> FlattenArray<T> {
         self.buf.clone()
     }
 
+    #[inline]
+    pub fn as_slice(&self) -> &[T] {
+        &self.buf
+    }
+
     #[inline]
     pub fn into_vec(self) -> Vec<T> {
         self.buf
@@ -1,11 +1,9 @@
+use std::convert::TryFrom;
 use std::path::Path;
 
-use image::GenericImageView;
-use std::simd::u16x8;
-
 use crate::Result;
 
-use super::{FlattenArray, Screenshot};
+use super::{bgr_to_luma, FlattenArray, Screenshot};
 
 pub(super) struct GrayImage {
     width: u32,
@@ -14,32 +12,41 @@ pub(super) struct GrayImage {
 }
 
 impl GrayImage {
-    pub fn from_screenshot(screenshot: &Screenshot) -> Self {
-        let width = screenshot.width();
-        let height = screenshot.height();
-        let mut buf = FlattenArray::new(width as usize, height as usize, 0u8);
-        for y in 0..height {
-            for x in 0..width {
-                buf[(y as usize, x as usize)] = screenshot.pixel(x, y).luma();
+    /// Converts the screenshot to luma and averages every `factor`×`factor`
+    /// block in a single pass, dropping the pixels of incomplete blocks.
+    pub fn from_screenshot_compressed(screenshot: &Screenshot, factor: u32) -> Self {
+        let width = screenshot.width() / factor;
+        let height = screenshot.height() / factor;
+        let (factor, row_len) = (factor as usize, screenshot.width() as usize * 4);
+
+        let mut buf = Vec::with_capacity(width as usize * height as usize);
+        let mut lumas = vec![0u8; width as usize * factor];
+        let mut sums = vec![0u32; width as usize];
+        for rows in screenshot
+            .bgra_buf()
+            .chunks_exact(row_len * factor)
+            .take(height as usize)
+        {
+            sums.iter_mut().for_each(|sum| *sum = 0);
+            for row in rows.chunks_exact(row_len) {
+                for (luma, bgra) in lumas.iter_mut().zip(row.chunks_exact(4)) {
+                    *luma = bgr_to_luma(bgra[0], bgra[1], bgra[2]);
+                }
+                for (sum, block) in sums.iter_mut().zip(lumas.chunks_exact(factor)) {
+                    *sum += block.iter().map(|&luma| luma as u32).sum::<u32>();
+                }
             }
+            let area = (factor * factor) as u32;
+            buf.extend(sums.iter().map(|&sum| (sum / area) as u8));
         }
-        Self { width, height, buf }
-    }
 
-    #[inline]
-    pub fn from_file_buf(buf: &[u8]) -> Result<Self> {
-        match image::load_from_memory(buf) {
-            Ok(dyn_img) => Ok(Self::from_raw(
-                dyn_img.width(),
-                dyn_img.height(),
-                dyn_img.into_luma8().into_raw(),
-            )
-            .unwrap()),
-            Err(_) => Err("Unknown error".to_string()),
+        Self {
+            width,
+            height,
+            buf: FlattenArray::from_vec(width as usize, buf),
         }
     }
 
-    #[inline]
     pub fn from_raw(width: u32, height: u32, buf: Vec<u8>) -> Result<Self> {
         if width * height != buf.len() as u32 {
             return Err("Unknown error".to_string());
@@ -68,36 +75,20 @@ impl GrayImage {
         self.buf[(y as usize, x as usize)]
     }
 
+    /// Lumas of the pixels, row by row.
     #[inline]
-    pub fn to_redundant_packed(&self) -> RedundantPackedGrayImage {
-        RedundantPackedGrayImage::from_gray_image(self)
+    pub fn as_bytes(&self) -> &[u8] {
+        self.buf.as_slice()
     }
 
     #[inline]
-    pub fn to_packed(&self) -> PackedGrayImage {
-        PackedGrayImage::from_gray_image(self)
+    pub fn into_padded(self) -> PaddedGrayImage {
+        PaddedGrayImage::from_gray_image(self)
     }
 
     #[inline]
-    pub fn into_compressed(self, factor: u32) -> Self {
-        let width = self.width / factor;
-        let height = self.height / factor;
-        let mut buf = FlattenArray::new(width as usize, height as usize, 0u32);
-
-        for y in 0..self.height / factor * factor {
-            for x in 0..self.width / factor * factor {
-                buf[((y / factor) as usize, (x / factor) as usize)] += self.pixel(x, y) as u32;
-            }
-        }
-
-        let buf = buf
-            .into_vec()
-            .into_iter()
-            .map(|v| (v / factor / factor) as u8)
-            .collect();
-        let buf = FlattenArray::from_vec(width as usize, buf);
-
-        Self { width, height, buf }
+    pub fn to_packed(&self) -> PackedGrayImage {
+        PackedGrayImage::from_gray_image(self)
     }
 
     #[inline]
@@ -110,78 +101,131 @@ impl GrayImage {
     }
 }
 
-pub(super) struct RedundantPackedGrayImage {
-    buf: FlattenArray<u16x8>,
-}
+/// Number of pixels processed by one vector operation, chosen by the widest
+/// vector registers the target supports.
+#[cfg(target_feature = "avx512bw")]
+pub(super) const LANES: usize = 32;
+#[cfg(all(target_feature = "avx2", not(target_feature = "avx512bw")))]
+pub(super) const LANES: usize = 16;
+#[cfg(not(target_feature = "avx2"))]
+pub(super) const LANES: usize = 8;
 
-impl RedundantPackedGrayImage {
-    const PACK: usize = 8;
+pub(super) type Lumas = [u8; LANES];
 
-    #[inline]
-    pub fn from_gray_image(image: &GrayImage) -> Self {
-        let width = image.width();
-        let height = image.height();
+/// A gray image with `LANES` zeroed pixels appended, so that `LANES` pixels
+/// can be loaded from any position without bounds checking the tail.
+pub(super) struct PaddedGrayImage {
+    width: u32,
+    height: u32,
+    buf: Vec<u8>,
+}
 
-        let mut buf = FlattenArray::new(width as usize, height as usize, u16x8::from([0u16; 8]));
-        for y in 0..height {
-            for x in 0..width {
-                let mut pixels = [0u16; Self::PACK];
-                for (i, pixel) in pixels
-                    .iter_mut()
-                    .enumerate()
-                    .take(Self::PACK.min((width - x) as usize))
-                {
-                    *pixel = image.pixel(x + i as u32, y) as u16;
-                }
-                buf[(y as usize, x as usize)] = u16x8::from(pixels);
-            }
-        }
+impl PaddedGrayImage {
+    #[inline]
+    pub fn from_gray_image(image: GrayImage) -> Self {
+        let (width, height) = (image.width, image.height);
+        let mut buf = image.buf.into_vec();
+        buf.resize(buf.len() + LANES, 0);
+        Self { width, height, buf }
+    }
 
-        Self { buf }
+    #[inline]
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+    #[inline]
+    pub fn height(&self) -> u32 {
+        self.height
     }
 
+    /// Loads `LANES` pixels starting at (x, y), wrapping to the next row if
+    /// the row ends.
     #[inline]
-    pub fn pixels(&self, x: u32, y: u32) -> &u16x8 {
-        &self.buf[(y as usize, x as usize)]
+    pub fn pixels(&self, x: u32, y: u32) -> &Lumas {
+        let head = y as usize * self.width as usize + x as usize;
+        <&Lumas>::try_from(&self.buf[head..head + LANES]).unwrap()
     }
 }
 
+/// A gray image whose rows are split into chunks of `LANES` pixels, with the
+/// last chunk of each row padded by zeros.
 pub(super) struct PackedGrayImage {
-    buf: FlattenArray<u16x8>,
+    width: u32,
+    height: u32,
+    buf: FlattenArray<Lumas>,
 }
 
 impl PackedGrayImage {
-    const PACK: usize = 8;
-
     #[inline]
     pub fn from_gray_image(image: &GrayImage) -> Self {
         let width = image.width();
         let height = image.height();
 
-        let mut packed_width = width as usize / Self::PACK;
-        if width as usize % Self::PACK != 0 {
-            packed_width += 1;
-        }
-        let mut buf = FlattenArray::new(packed_width, height as usize, u16x8::from([0u16; 8]));
+        let packed_width = (width as usize).div_ceil(LANES);
+        let mut buf = FlattenArray::new(packed_width, height as usize, [0u8; LANES]);
         for y in 0..height {
-            for x in (0..width).step_by(Self::PACK) {
-                let mut pixels = [0u16; Self::PACK];
+            for x in (0..width).step_by(LANES) {
+                let mut pixels = [0u8; LANES];
                 for (i, pixel) in pixels
                     .iter_mut()
                     .enumerate()
-                    .take(Self::PACK.min((width - x) as usize))
+                    .take(LANES.min((width - x) as usize))
                 {
-                    *pixel = image.pixel(x + i as u32, y) as u16;
+                    *pixel = image.pixel(x + i as u32, y);
                 }
-                buf[(y as usize, x as usize / Self::PACK)] = u16x8::from(pixels);
+                buf[(y as usize, x as usize / LANES)] = pixels;
             }
         }
 
-        Self { buf }
+        Self { width, height, buf }
+    }
+
+    #[inline]
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+    #[inline]
+    pub fn height(&self) -> u32 {
+        self.height
     }
 
+    /// Returns the chunk containing (x, y), `x` is expected to be a multiple
+    /// of `LANES`.
     #[inline]
-    pub fn pixels(&self, x: u32, y: u32) -> &u16x8 {
-        &self.buf[(y as usize, x as usize / Self::PACK)]
+    pub fn pixels(&self, x: u32, y: u32) -> &Lumas {
+        &self.buf[(y as usize, x as usize / LANES)]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compress_screenshot_by_blocks() {
+        let (width, height) = (23, 17);
+        let bgra_buf = (0..width * height * 4)
+            .map(|i| (i * 37 % 251) as u8)
+            .collect();
+        let screenshot = Screenshot::from_bgra_buf(width, height, bgra_buf).unwrap();
+
+        for factor in 1..6 {
+            let image = GrayImage::from_screenshot_compressed(&screenshot, factor);
+            assert_eq!(image.width(), width / factor);
+            assert_eq!(image.height(), height / factor);
+
+            for y in 0..image.height() {
+                for x in 0..image.width() {
+                    let mut sum = 0u32;
+                    for dy in 0..factor {
+                        for dx in 0..factor {
+                            let pixel = screenshot.pixel(x * factor + dx, y * factor + dy);
+                            sum += pixel.luma() as u32;
+                        }
+                    }
+                    assert_eq!(image.pixel(x, y) as u32, sum / factor / factor);
+                }
+            }
+        }
     }
 }
@@ -0,0 +1,216 @@
+use crate::geometry::{Point, Size};
+
+use super::{Finder, Pattern};
+
+/// Matches laid out in rows and columns, such as the slots of an inventory
+/// or the rows of a list, indexed from the top left cell.
+///
+/// Cells without a match, such as an empty slot, still have a position
+/// inferred from the pitch of the grid.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Grid {
+    origin: Point,
+    pitch: Size,
+    rows: u32,
+    cols: u32,
+    cells: Vec<Option<Point>>,
+}
+
+impl Grid {
+    // Cells allowed per match, past which most of the grid would be empty,
+    // as when one match off the pitch shrinks it
+    const MAX_CELLS_PER_MATCH: usize = 4;
+
+    /// Clusters the centers of matches into rows and columns, where centers
+    /// less than `tolerance` apart along an axis are in the same band.
+    ///
+    /// The pitch is the smallest distance between neighbouring bands, so
+    /// missing rows or columns are kept as empty cells. `None` without
+    /// matches, for a zero `tolerance` or if most cells would be empty.
+    pub fn from_matches(matches: &[Point], tolerance: u32) -> Option<Grid> {
+        if tolerance == 0 {
+            return None;
+        }
+        let ys = Self::bands(matches.iter().map(|pos| pos.y), tolerance);
+        let xs = Self::bands(matches.iter().map(|pos| pos.x), tolerance);
+        let (&top, &left) = (ys.first()?, xs.first()?);
+        let pitch = Size::new(Self::band_pitch(&xs), Self::band_pitch(&ys));
+
+        let index = |value: u32, first: u32, pitch: u32| match pitch {
+            0 => 0,
+            pitch => ((value - first) as f64 / pitch as f64).round() as u32,
+        };
+        let rows = index(*ys.last()?, top, pitch.height).checked_add(1)?;
+        let cols = index(*xs.last()?, left, pitch.width).checked_add(1)?;
+
+        let len = (rows as usize).checked_mul(cols as usize)?;
+        if len > matches.len().saturating_mul(Self::MAX_CELLS_PER_MATCH) {
+            return None;
+        }
+        let mut cells = vec![None; len];
+        for &pos in matches {
+            let row = index(pos.y.max(top), top, pitch.height).min(rows - 1);
+            let col = index(pos.x.max(left), left, pitch.width).min(cols - 1);
+            cells[row as usize * cols as usize + col as usize].get_or_insert(pos);
+        }
+
+        Some(Grid {
+            origin: Point::new(left, top),
+            pitch,
+            rows,
+            cols,
+            cells,
+        })
+    }
+
+    // Means of the values grouped into bands, in increasing order
+    fn bands<I: Iterator<Item = u32>>(values: I, tolerance: u32) -> Vec<u32> {
+        let mut values: Vec<_> = values.collect();
+        values.sort_unstable();
+
+        let mut bands: Vec<(u64, u64)> = vec![];
+        let mut last = None;
+        for value in values {
+            match (bands.last_mut(), last) {
+                (Some((sum, count)), Some(last)) if value - last < tolerance => {
+                    *sum += value as u64;
+                    *count += 1;
+                }
+                _ => bands.push((value as u64, 1)),
+            }
+            last = Some(value);
+        }
+        bands
+            .into_iter()
+            .map(|(sum, count)| (sum / count) as u32)
+            .collect()
+    }
+
+    fn band_pitch(bands: &[u32]) -> u32 {
+        bands
+            .windows(2)
+            .map(|pair| pair[1] - pair[0])
+            .min()
+            .unwrap_or(0)
+    }
+
+    pub fn rows(&self) -> u32 {
+        self.rows
+    }
+
+    pub fn cols(&self) -> u32 {
+        self.cols
+    }
+
+    /// Distance between the centers of neighbouring cells, zero along an
+    /// axis of a single band.
+    pub fn pitch(&self) -> Size {
+        self.pitch
+    }
+
+    /// Returns the match in a cell, if any.
+    pub fn cell(&self, row: u32, col: u32) -> Option<Point> {
+        if row >= self.rows || col >= self.cols {
+            return None;
+        }
+        self.cells[(row * self.cols + col) as usize]
+    }
+
+    /// Returns the inferred center of a cell, which may lie outside of the
+    /// grid.
+    pub fn cell_center(&self, row: u32, col: u32) -> Point {
+        Point::new(
+            self.origin.x + col * self.pitch.width,
+            self.origin.y + row * self.pitch.height,
+        )
+    }
+
+    /// Iterates over the matched cells as `(row, col, center)`, row by row.
+    pub fn iter(&self) -> impl Iterator<Item = (u32, u32, Point)> + '_ {
+        let cols = self.cols;
+        self.cells
+            .iter()
+            .enumerate()
+            .filter_map(move |(index, cell)| {
+                cell.map(|pos| (index as u32 / cols, index as u32 % cols, pos))
+            })
+    }
+}
+
+impl<'a> Finder<'a> {
+    /// Finds every match of `pattern` and lays them out in a grid, see
+    /// `Grid::from_matches`. Matches closer than half the pattern size share
+    /// a band.
+    pub fn find_grid(&self, pattern: &Pattern) -> Option<Grid> {
+        let size = pattern.size();
+        let tolerance = size.width.min(size.height).div_ceil(2);
+        Grid::from_matches(&self.find_all(pattern), tolerance)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{PixelFormat, Screenshot};
+    use super::*;
+
+    #[test]
+    fn cluster_matches() {
+        // Three rows of four slots, 60 by 50 pixels apart, with one empty
+        let mut matches = vec![];
+        for row in 0..3 {
+            for col in 0..4 {
+                if (row, col) != (1, 2) {
+                    matches.push(Point::new(
+                        100 + col * 60 + row % 2,
+                        40 + row * 50 + col % 2,
+                    ));
+                }
+            }
+        }
+        let grid = Grid::from_matches(&matches, 10).unwrap();
+        assert_eq!((grid.rows(), grid.cols()), (3, 4));
+        assert_eq!(grid.pitch(), Size::new(60, 50));
+        assert_eq!(grid.cell(2, 1), Some(Point::new(160, 141)));
+        assert_eq!(grid.cell(1, 2), None);
+        assert_eq!(grid.cell_center(1, 2), Point::new(220, 90));
+        assert_eq!(grid.iter().count(), 11);
+        assert_eq!(Grid::from_matches(&[], 10), None);
+        assert_eq!(Grid::from_matches(&matches, 0), None);
+
+        // A match one pixel off the first sets a pitch of one pixel
+        let matches = [
+            Point::new(0, 0),
+            Point::new(1, 1),
+            Point::new(100_000, 100_000),
+        ];
+        assert_eq!(Grid::from_matches(&matches, 1), None);
+    }
+
+    #[test]
+    fn find_list_items() {
+        let (width, height) = (60u32, 200u32);
+        let mut buf = vec![0u8; (width * height) as usize];
+        for y in 0..height {
+            for x in 0..width {
+                // Items of 20 pixels every 30 pixels from 10
+                if (10..50).contains(&x) && (10..190).contains(&y) && (y + 20) % 30 < 20 {
+                    buf[(y * width + x) as usize] = ((x * 5 + (y + 20) % 30 * 11) % 251) as u8;
+                }
+            }
+        }
+        let screenshot =
+            Screenshot::from_raw(width, height, width as usize, PixelFormat::Gray, &buf).unwrap();
+        let item = Screenshot::from_raw(40, 20, width as usize, PixelFormat::Gray, {
+            let head = (10 * width + 10) as usize;
+            &buf[head..]
+        })
+        .unwrap();
+        let pattern = Pattern::from_screenshot(&item);
+
+        let finder = Finder::new(&screenshot);
+        assert_eq!(finder.find_all(&pattern).len(), 6);
+        let grid = finder.find_grid(&pattern).unwrap();
+        assert_eq!((grid.rows(), grid.cols()), (6, 1));
+        assert_eq!(grid.pitch().height, 30);
+    }
+}
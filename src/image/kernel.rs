@@ -0,0 +1,208 @@
+use super::{PackedGrayImage, PaddedGrayImage, LANES};
+
+/// Number of rows between two checks of the rejection callback.
+pub(super) const CHECK_ROWS: u32 = 4;
+
+#[cfg(not(feature = "simd"))]
+pub(super) use scalar::window_dot;
+#[cfg(feature = "simd")]
+pub(super) use simd::window_dot;
+
+/// Kernel written with plain arrays, which compilers are able to vectorize on
+/// stable Rust.
+#[cfg_attr(feature = "simd", allow(dead_code))]
+mod scalar {
+    use super::{PackedGrayImage, PaddedGrayImage, CHECK_ROWS, LANES};
+
+    /// Correlates the pattern with the window at (x, y).
+    ///
+    /// Products are accumulated in `u32` lanes and only reduced every
+    /// `CHECK_ROWS` rows, where `proceed` is given the number of rows done and
+    /// the partial dot product. The window is abandoned once it returns false.
+    #[inline]
+    pub fn window_dot<F>(
+        image: &PaddedGrayImage,
+        pattern: &PackedGrayImage,
+        x: u32,
+        y: u32,
+        mut proceed: F,
+    ) -> Option<u32>
+    where
+        F: FnMut(u32, u32) -> bool,
+    {
+        let mut acc = [0u32; LANES];
+        for dy in 0..pattern.height() {
+            for dx in (0..pattern.width()).step_by(LANES) {
+                let image_values = image.pixels(x + dx, y + dy);
+                let pattern_values = pattern.pixels(dx, dy);
+
+                for ((acc, a), b) in acc.iter_mut().zip(image_values).zip(pattern_values) {
+                    // A product of two lumas always fits in `u16`
+                    *acc += (*a as u16 * *b as u16) as u32;
+                }
+            }
+
+            let rest = dy + 1;
+            if rest % CHECK_ROWS == 0 && rest < pattern.height() && !proceed(rest, acc.iter().sum())
+            {
+                return None;
+            }
+        }
+        Some(acc.iter().sum())
+    }
+}
+
+/// Kernel written with `std::simd`, which requires a nightly toolchain.
+#[cfg(feature = "simd")]
+mod simd {
+    use std::simd::num::SimdUint;
+    use std::simd::Simd;
+
+    use super::{PackedGrayImage, PaddedGrayImage, CHECK_ROWS, LANES};
+
+    /// Correlates the pattern with the window at (x, y).
+    ///
+    /// Behaves exactly like the scalar kernel.
+    #[inline]
+    pub fn window_dot<F>(
+        image: &PaddedGrayImage,
+        pattern: &PackedGrayImage,
+        x: u32,
+        y: u32,
+        mut proceed: F,
+    ) -> Option<u32>
+    where
+        F: FnMut(u32, u32) -> bool,
+    {
+        let mut acc = Simd::<u32, LANES>::splat(0);
+        for dy in 0..pattern.height() {
+            for dx in (0..pattern.width()).step_by(LANES) {
+                let image_values = Simd::from_array(*image.pixels(x + dx, y + dy)).cast::<u16>();
+                let pattern_values = Simd::from_array(*pattern.pixels(dx, dy)).cast::<u16>();
+
+                // A product of two lumas always fits in `u16`
+                acc += (image_values * pattern_values).cast::<u32>();
+            }
+
+            let rest = dy + 1;
+            if rest % CHECK_ROWS == 0 && rest < pattern.height() && !proceed(rest, acc.reduce_sum())
+            {
+                return None;
+            }
+        }
+        Some(acc.reduce_sum())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::GrayImage;
+    use super::*;
+
+    struct Lcg(u64);
+
+    impl Lcg {
+        fn next(&mut self, m: u32) -> u32 {
+            self.0 = self
+                .0
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            ((self.0 >> 33) % m as u64) as u32
+        }
+
+        fn buf(&mut self, len: u32) -> Vec<u8> {
+            (0..len).map(|_| self.next(256) as u8).collect()
+        }
+
+        fn gray_image(&mut self, width: u32, height: u32) -> GrayImage {
+            let buf = self.buf(width * height);
+            GrayImage::from_raw(width, height, buf).unwrap()
+        }
+    }
+
+    fn naive_dot(image: &GrayImage, pattern: &GrayImage, x: u32, y: u32, rows: u32) -> u32 {
+        let mut dot = 0u32;
+        for dy in 0..rows {
+            for dx in 0..pattern.width() {
+                dot += image.pixel(x + dx, y + dy) as u32 * pattern.pixel(dx, dy) as u32;
+            }
+        }
+        dot
+    }
+
+    #[test]
+    fn scalar_matches_naive() {
+        let mut rng = Lcg(7);
+        for _ in 0..20 {
+            let (width, height) = (1 + rng.next(40), 1 + rng.next(30));
+            let (image_width, image_height) = (width + rng.next(20), height + rng.next(20));
+            let image_buf = rng.buf(image_width * image_height);
+            let image = GrayImage::from_raw(image_width, image_height, image_buf.clone()).unwrap();
+            let padded = GrayImage::from_raw(image_width, image_height, image_buf)
+                .unwrap()
+                .into_padded();
+            let pattern = rng.gray_image(width, height);
+            let packed = pattern.to_packed();
+            let (x, y) = (
+                rng.next(image_width - width + 1),
+                rng.next(image_height - height + 1),
+            );
+
+            let expected = naive_dot(&image, &pattern, x, y, height);
+            let mut checks = vec![];
+            let dot = scalar::window_dot(&padded, &packed, x, y, |rest, dot| {
+                checks.push((rest, dot));
+                true
+            });
+
+            assert_eq!(dot, Some(expected));
+            for (rest, dot) in checks {
+                assert_eq!(rest % CHECK_ROWS, 0);
+                assert_eq!(dot, naive_dot(&image, &pattern, x, y, rest));
+            }
+        }
+    }
+
+    #[test]
+    fn rejected_window_stops() {
+        let mut rng = Lcg(11);
+        let image = rng.gray_image(64, 64).into_padded();
+        let pattern = rng.gray_image(16, 16).to_packed();
+        let mut calls = 0;
+        let dot = window_dot(&image, &pattern, 3, 5, |_, _| {
+            calls += 1;
+            false
+        });
+        assert_eq!(dot, None);
+        assert_eq!(calls, 1);
+    }
+
+    #[cfg(feature = "simd")]
+    #[test]
+    fn simd_matches_scalar() {
+        let mut rng = Lcg(13);
+        for _ in 0..20 {
+            let (width, height) = (1 + rng.next(70), 1 + rng.next(30));
+            let (image_width, image_height) = (width + rng.next(20), height + rng.next(20));
+            let image = rng.gray_image(image_width, image_height).into_padded();
+            let pattern = rng.gray_image(width, height).to_packed();
+            let (x, y) = (
+                rng.next(image_width - width + 1),
+                rng.next(image_height - height + 1),
+            );
+
+            let (mut scalar_checks, mut simd_checks) = (vec![], vec![]);
+            let scalar_dot = scalar::window_dot(&image, &pattern, x, y, |rest, dot| {
+                scalar_checks.push((rest, dot));
+                true
+            });
+            let simd_dot = simd::window_dot(&image, &pattern, x, y, |rest, dot| {
+                simd_checks.push((rest, dot));
+                true
+            });
+
+            assert_eq!(scalar_dot, simd_dot);
+            assert_eq!(scalar_checks, simd_checks);
+        }
+    }
+}
@@ -0,0 +1,503 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use crate::geometry::{Anchor, Rect};
+use crate::Result;
+
+use super::{Hotspot, Pattern, Screenshot};
+
+/// Patterns loaded from the images of a directory tree, named by their path
+/// relative to the directory without extension, such as `buttons/ok` for
+/// `buttons/ok.png`.
+///
+/// A pattern may come with a sidecar file of the same name and the `meta`
+/// extension, with one `key = value` setting per line and `#` comments:
+///
+/// ```text
+/// threshold = 0.95
+/// # An anchor and an offset, or a pixel of the image
+/// hotspot = right 200 0
+/// # In pixels of the captured frames
+/// region = 0 0 640 80
+/// # Relative to the sidecar
+/// mask = ok.mask.png
+/// ```
+///
+/// Images ending in `.mask.png` are masks rather than patterns, and one named
+/// after a pattern is its mask unless the sidecar tells otherwise.
+pub struct PatternLibrary {
+    dir: PathBuf,
+    scale: f64,
+    on_load: Option<LoadHook>,
+    patterns: BTreeMap<String, Arc<Pattern>>,
+    // Files each pattern was last loaded from, or looked for, with their
+    // stamps, including after a failed load so that it is retried only once
+    // they change
+    sources: BTreeMap<String, Sources>,
+}
+
+/// Adjusts a pattern loaded by a `PatternLibrary`, see `load_with`.
+pub type LoadHook = Box<dyn Fn(&mut Pattern)>;
+
+type Sources = Vec<(PathBuf, Option<Stamp>)>;
+
+// Modification time and length of a file, to tell whether it changed
+type Stamp = (SystemTime, u64);
+
+fn stamp(path: &Path) -> Option<Stamp> {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+/// Outcome of `PatternLibrary::reload_changed`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Reload {
+    reloaded: Vec<String>,
+    removed: Vec<String>,
+    errors: Vec<String>,
+}
+
+impl Reload {
+    /// Names of the patterns loaded again or for the first time.
+    pub fn reloaded(&self) -> &[String] {
+        &self.reloaded
+    }
+
+    /// Names of the patterns whose image was removed.
+    pub fn removed(&self) -> &[String] {
+        &self.removed
+    }
+
+    /// Failures to load changed patterns, which keep their previous version.
+    /// A failure is reported once, until the files of the pattern change.
+    pub fn errors(&self) -> &[String] {
+        &self.errors
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.reloaded.is_empty() && self.removed.is_empty() && self.errors.is_empty()
+    }
+}
+
+impl PatternLibrary {
+    const EXTENSIONS: [&'static str; 4] = ["png", "bmp", "jpg", "jpeg"];
+    const MASK_SUFFIX: &'static str = ".mask.png";
+    const SIDECAR_EXTENSION: &'static str = "meta";
+
+    pub fn load<P: AsRef<Path>>(dir: P) -> Result<Self> {
+        Self::load_scaled(dir, 1.0)
+    }
+
+    /// Like `load`, with the images and masks resized by `scale`, see
+    /// `Pattern::from_file_buf_scaled`.
+    pub fn load_scaled<P: AsRef<Path>>(dir: P, scale: f64) -> Result<Self> {
+        Self::load_with(dir, scale, None)
+    }
+
+    /// Like `load_scaled`, calling `on_load` with every pattern loaded,
+    /// including the reloaded ones, before it is used.
+    pub fn load_with<P: AsRef<Path>>(
+        dir: P,
+        scale: f64,
+        on_load: Option<LoadHook>,
+    ) -> Result<Self> {
+        let mut library = Self {
+            dir: dir.as_ref().to_path_buf(),
+            scale,
+            on_load,
+            patterns: BTreeMap::new(),
+            sources: BTreeMap::new(),
+        };
+        for (name, path) in library.scan()? {
+            let mut sources = vec![];
+            let pattern = library
+                .load_pattern(&path, &mut sources)
+                .map_err(|err| format!("{}: {}", path.display(), err))?;
+            library.patterns.insert(name.clone(), pattern);
+            library.sources.insert(name, sources);
+        }
+        Ok(library)
+    }
+
+    /// Loads the patterns whose image, sidecar or mask changed since they
+    /// were loaded, as well as new images, and drops the ones whose image was
+    /// removed.
+    ///
+    /// Each pattern is replaced at once, only after it loaded successfully,
+    /// so a pattern that fails to load keeps its previous version.
+    pub fn reload_changed(&mut self) -> Reload {
+        let mut reload = Reload::default();
+        let images = match self.scan() {
+            Ok(images) => images,
+            Err(err) => {
+                reload.errors.push(err);
+                return reload;
+            }
+        };
+
+        let removed: Vec<String> = self
+            .sources
+            .keys()
+            .filter(|name| !images.contains_key(*name))
+            .cloned()
+            .collect();
+        for name in removed {
+            self.sources.remove(&name);
+            if self.patterns.remove(&name).is_some() {
+                reload.removed.push(name);
+            }
+        }
+
+        for (name, path) in images {
+            let changed = match self.sources.get(&name) {
+                Some(sources) => sources.iter().any(|(source, last)| stamp(source) != *last),
+                None => true,
+            };
+            if !changed {
+                continue;
+            }
+            let mut sources = vec![];
+            match self.load_pattern(&path, &mut sources) {
+                Ok(pattern) => {
+                    self.patterns.insert(name.clone(), pattern);
+                    reload.reloaded.push(name.clone());
+                }
+                Err(err) => reload.errors.push(format!("{}: {}", path.display(), err)),
+            }
+            self.sources.insert(name, sources);
+        }
+        reload
+    }
+
+    // Paths of the pattern images in the directory tree by name, failing if
+    // two images have the same name
+    fn scan(&self) -> Result<BTreeMap<String, PathBuf>> {
+        let mut files = vec![];
+        Self::collect_images(&self.dir, &mut files)?;
+        let mut images: BTreeMap<String, PathBuf> = BTreeMap::new();
+        for path in files {
+            let name = Self::name(&self.dir, &path);
+            if let Some(other) = images.get(&name) {
+                return Err(format!(
+                    "{} and {} are both named `{}`",
+                    other.display(),
+                    path.display(),
+                    name
+                ));
+            }
+            images.insert(name, path);
+        }
+        Ok(images)
+    }
+
+    fn collect_images(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+        let entries = fs::read_dir(dir).map_err(|err| format!("{}: {}", dir.display(), err))?;
+        let mut paths = entries
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<std::io::Result<Vec<_>>>()
+            .map_err(|err| format!("{}: {}", dir.display(), err))?;
+        paths.sort();
+
+        for path in paths {
+            if path.is_dir() {
+                Self::collect_images(&path, files)?;
+            } else if Self::is_pattern_image(&path) {
+                files.push(path);
+            }
+        }
+        Ok(())
+    }
+
+    fn is_pattern_image(path: &Path) -> bool {
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+        let extension = path
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase());
+        !file_name.to_lowercase().ends_with(Self::MASK_SUFFIX)
+            && matches!(extension, Some(extension) if Self::EXTENSIONS.contains(&extension.as_str()))
+    }
+
+    // Relative path without extension, with `/` as separator
+    fn name(dir: &Path, path: &Path) -> String {
+        let relative = path.strip_prefix(dir).unwrap().with_extension("");
+        relative
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+
+    // Loads a pattern, adding the files it is made of to `sources` before
+    // reading them
+    fn load_pattern(&self, path: &Path, sources: &mut Sources) -> Result<Arc<Pattern>> {
+        let sidecar = path.with_extension(Self::SIDECAR_EXTENSION);
+        let default_mask = path.with_extension("mask.png");
+        for source in [path, &sidecar, &default_mask] {
+            sources.push((source.to_path_buf(), stamp(source)));
+        }
+
+        let buf = fs::read(path).map_err(|err| err.to_string())?;
+        let mut pattern = Pattern::from_file_buf_scaled(&buf, self.scale)?;
+
+        let mut mask = Some(default_mask).filter(|mask| mask.is_file());
+        if sidecar.is_file() {
+            let text = fs::read_to_string(&sidecar).map_err(|err| err.to_string())?;
+            for (number, line) in text.lines().enumerate() {
+                let line = line.split('#').next().unwrap().trim();
+                if line.is_empty() {
+                    continue;
+                }
+                Self::apply_setting(&mut pattern, &mut mask, path, line)
+                    .map_err(|err| format!("{} line {}: {}", sidecar.display(), number + 1, err))?;
+            }
+        }
+
+        if let Some(mask) = mask {
+            if !sources.iter().any(|(source, _)| *source == mask) {
+                sources.push((mask.clone(), stamp(&mask)));
+            }
+            let buf = fs::read(&mask).map_err(|err| format!("{}: {}", mask.display(), err))?;
+            pattern.set_mask(&Screenshot::from_file_buf(&buf)?);
+        }
+        if let Some(hook) = &self.on_load {
+            hook(&mut pattern);
+        }
+        Ok(Arc::new(pattern))
+    }
+
+    fn apply_setting(
+        pattern: &mut Pattern,
+        mask: &mut Option<PathBuf>,
+        path: &Path,
+        line: &str,
+    ) -> Result<()> {
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| "Expected `key = value`".to_string())?;
+        let value = value.trim();
+        let words: Vec<&str> = value.split_whitespace().collect();
+        match key.trim() {
+            "threshold" => {
+                let threshold: f32 = parse(value)?;
+                if !(0.0..=1.0).contains(&threshold) {
+                    return Err(format!("Expected a threshold from 0 to 1, not {}", value));
+                }
+                pattern.set_threshold(threshold);
+            }
+            "hotspot" => {
+                let hotspot = match words.as_slice() {
+                    [x, y] => Hotspot::at(parse(x)?, parse(y)?),
+                    [anchor] => Hotspot::new(parse_anchor(anchor)?, 0, 0),
+                    [anchor, dx, dy] => Hotspot::new(parse_anchor(anchor)?, parse(dx)?, parse(dy)?),
+                    _ => return Err("Expected an anchor and an offset, or a pixel".to_string()),
+                };
+                pattern.set_hotspot(hotspot);
+            }
+            "region" => match words.as_slice() {
+                [x, y, width, height] => pattern.set_region(Some(Rect::new(
+                    parse(x)?,
+                    parse(y)?,
+                    parse(width)?,
+                    parse(height)?,
+                ))),
+                _ => return Err("Expected x, y, width and height".to_string()),
+            },
+            "mask" => *mask = Some(path.parent().unwrap().join(value)),
+            key => return Err(format!("Unknown setting `{}`", key)),
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Pattern> {
+        self.patterns.get(name).map(|pattern| &**pattern)
+    }
+
+    /// Like `get`, failing with the name of a missing pattern.
+    pub fn pattern(&self, name: &str) -> Result<&Pattern> {
+        self.get(name)
+            .ok_or_else(|| format!("No pattern named `{}`", name))
+    }
+
+    /// Returns a pattern that outlives its reloading, such as to keep using
+    /// it while the library is reloaded.
+    pub fn shared(&self, name: &str) -> Option<Arc<Pattern>> {
+        self.patterns.get(name).cloned()
+    }
+
+    /// Names of the patterns in order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.patterns.keys().map(|name| name.as_str())
+    }
+
+    pub fn len(&self) -> usize {
+        self.patterns.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty()
+    }
+}
+
+/// A pattern given directly or by its name in a `PatternLibrary`, which
+/// follows the library as it is reloaded.
+pub enum PatternRef<'a> {
+    Borrowed(&'a Pattern),
+    Named(String),
+}
+
+impl<'a> PatternRef<'a> {
+    /// Returns the pattern, taking a named one from `library`. A missing
+    /// pattern is `None`, as it may be added by a reload.
+    pub fn resolve(&self, library: Option<&PatternLibrary>) -> Option<PatternHandle<'a>> {
+        match self {
+            PatternRef::Borrowed(pattern) => Some(PatternHandle::Borrowed(pattern)),
+            PatternRef::Named(name) => library?.shared(name).map(PatternHandle::Shared),
+        }
+    }
+}
+
+impl<'a> From<&'a Pattern> for PatternRef<'a> {
+    fn from(pattern: &'a Pattern) -> Self {
+        PatternRef::Borrowed(pattern)
+    }
+}
+
+impl<'a> From<&str> for PatternRef<'a> {
+    fn from(name: &str) -> Self {
+        PatternRef::Named(name.to_string())
+    }
+}
+
+/// A pattern resolved from a `PatternRef`, which stays valid when the
+/// library is reloaded.
+pub enum PatternHandle<'a> {
+    Borrowed(&'a Pattern),
+    Shared(Arc<Pattern>),
+}
+
+impl<'a> Deref for PatternHandle<'a> {
+    type Target = Pattern;
+
+    fn deref(&self) -> &Pattern {
+        match self {
+            PatternHandle::Borrowed(pattern) => pattern,
+            PatternHandle::Shared(pattern) => pattern,
+        }
+    }
+}
+
+fn parse<T: std::str::FromStr>(value: &str) -> Result<T> {
+    value
+        .parse()
+        .map_err(|_| format!("Invalid value `{}`", value))
+}
+
+fn parse_anchor(value: &str) -> Result<Anchor> {
+    Ok(match value {
+        "top-left" => Anchor::TopLeft,
+        "top" => Anchor::Top,
+        "top-right" => Anchor::TopRight,
+        "left" => Anchor::Left,
+        "center" => Anchor::Center,
+        "right" => Anchor::Right,
+        "bottom-left" => Anchor::BottomLeft,
+        "bottom" => Anchor::Bottom,
+        "bottom-right" => Anchor::BottomRight,
+        _ => return Err(format!("Unknown anchor `{}`", value)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{Luma, Rgba, RgbaImage};
+
+    use super::*;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn load_directory_tree() {
+        let dir = TempDir::new("library");
+        fs::create_dir(dir.join("buttons")).unwrap();
+        let texture = image::GrayImage::from_fn(40, 20, |x, y| Luma([(x * 5 + y * 9) as u8]));
+        texture.save(dir.join("title.png")).unwrap();
+        texture.save(dir.join("buttons/ok.png")).unwrap();
+        RgbaImage::from_fn(40, 20, |x, _| match x < 20 {
+            true => Rgba([255, 255, 255, 255]),
+            false => Rgba([0, 0, 0, 255]),
+        })
+        .save(dir.join("buttons/ok.mask.png"))
+        .unwrap();
+        fs::write(
+            dir.join("buttons/ok.meta"),
+            "threshold = 0.9 # looser\nhotspot = right 200 0\n\nregion = 0 0 640 80\n",
+        )
+        .unwrap();
+
+        let library = PatternLibrary::load(&dir).unwrap();
+        assert_eq!(library.names().collect::<Vec<_>>(), ["buttons/ok", "title"]);
+        let ok = library.pattern("buttons/ok").unwrap();
+        assert_eq!(ok.threshold(), 0.9);
+        assert_eq!(ok.hotspot(), Hotspot::new(Anchor::Right, 200, 0));
+        assert_eq!(ok.region(), Some(Rect::new(0, 0, 640, 80)));
+        assert!(ok.mask().is_some());
+        assert!(library.get("title").unwrap().mask().is_none());
+        assert!(library.pattern("missing").is_err());
+
+        fs::write(dir.join("title.meta"), "hotspot = middle\n").unwrap();
+        let err = PatternLibrary::load(&dir).err().unwrap();
+        assert!(err.contains("title.meta line 1"), "{}", err);
+        for threshold in &["2", "NaN"] {
+            fs::write(
+                dir.join("title.meta"),
+                format!("threshold = {}\n", threshold),
+            )
+            .unwrap();
+            let err = PatternLibrary::load(&dir).err().unwrap();
+            assert!(err.contains("Expected a threshold"), "{}", err);
+        }
+
+        fs::remove_file(dir.join("title.meta")).unwrap();
+        texture.save(dir.join("title.jpg")).unwrap();
+        let err = PatternLibrary::load(&dir).err().unwrap();
+        assert!(err.contains("both named `title`"), "{}", err);
+    }
+
+    #[test]
+    fn reload_changed_files() {
+        let dir = TempDir::new("reload");
+        let texture =
+            |width| image::GrayImage::from_fn(width, 20, |x, y| Luma([(x * 5 + y * 9) as u8]));
+        texture(40).save(dir.join("ok.png")).unwrap();
+
+        let mut library = PatternLibrary::load(&dir).unwrap();
+        let before = library.shared("ok").unwrap();
+        assert!(library.reload_changed().is_empty());
+
+        texture(30).save(dir.join("ok.png")).unwrap();
+        texture(20).save(dir.join("new.png")).unwrap();
+        let reload = library.reload_changed();
+        assert_eq!(reload.reloaded(), ["new", "ok"]);
+        assert_eq!(library.get("ok").unwrap().size().width, 30);
+        assert_eq!(before.size().width, 40);
+
+        fs::write(dir.join("ok.meta"), "threshold = loose\n").unwrap();
+        fs::remove_file(dir.join("new.png")).unwrap();
+        let reload = library.reload_changed();
+        assert_eq!(reload.removed(), ["new"]);
+        assert_eq!(reload.errors().len(), 1);
+        assert!(
+            reload.errors()[0].contains("ok.meta line 1"),
+            "{:?}",
+            reload
+        );
+        assert_eq!(library.get("ok").unwrap().size().width, 30);
+
+        fs::write(dir.join("ok.meta"), "threshold = 0.9\n").unwrap();
+        assert_eq!(library.reload_changed().reloaded(), ["ok"]);
+        assert_eq!(library.get("ok").unwrap().threshold(), 0.9);
+    }
+}
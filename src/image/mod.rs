@@ -1,13 +1,22 @@
 mod flatten_array;
 mod gray_image;
+mod kernel;
 
 use flatten_array::*;
 use gray_image::*;
 
+mod diff;
 mod finder;
+mod grid;
+mod library;
 mod pattern;
+mod relation;
 mod screenshot;
 
+pub use diff::*;
 pub use finder::*;
+pub use grid::*;
+pub use library::*;
 pub use pattern::*;
+pub use relation::*;
 pub use screenshot::*;
@@ -1,43 +1,307 @@
+use std::convert::{TryFrom, TryInto};
+use std::fs;
 use std::path::Path;
 
-use std::simd::u16x8;
-
+use crate::geometry::{Anchor, Point, Rect, Size};
 use crate::Result;
 
-use super::{GrayImage, PackedGrayImage};
+use super::{GrayImage, PackedGrayImage, PixelFormat, Screenshot};
+
+/// Point of a pattern that actions target instead of its center, such as a
+/// field next to a matched label.
+///
+/// The offset is in pixels of the image the pattern was made from, and is
+/// scaled along with the pattern.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Hotspot {
+    anchor: Anchor,
+    offset: (i32, i32),
+}
+
+impl Hotspot {
+    /// Targets `anchor` of the pattern moved by `dx` and `dy`, which may lie
+    /// outside of the pattern.
+    pub fn new(anchor: Anchor, dx: i32, dy: i32) -> Self {
+        Self {
+            anchor,
+            offset: (dx, dy),
+        }
+    }
+
+    /// Targets the pixel at `x` and `y` of the pattern image.
+    pub fn at(x: u32, y: u32) -> Self {
+        Self::new(Anchor::TopLeft, x as i32, y as i32)
+    }
+
+    pub fn anchor(&self) -> Anchor {
+        self.anchor
+    }
+
+    pub fn offset(&self) -> (i32, i32) {
+        self.offset
+    }
+}
+
+impl Default for Hotspot {
+    fn default() -> Self {
+        Self::new(Anchor::Center, 0, 0)
+    }
+}
 
 pub struct Pattern {
+    checksum: u64,
+    size: Size,
+    scale: f64,
+    hotspot: Hotspot,
+    threshold: f32,
+    region: Option<Rect>,
+    // Pixels of the compressed image that are compared, if not all
+    mask: Option<Vec<bool>>,
     factor: u32,
     image: GrayImage,
     packed_image: PackedGrayImage,
+    sum: u64,
     square_sum: u64,
+    // Square sums of the rows from `y` to the bottom, used to bound the
+    // remaining part of a correlation
+    rest_square_sums: Vec<u64>,
 }
 
 impl Pattern {
     #[inline]
     pub fn from_file_buf(buf: &[u8]) -> Result<Self> {
-        GrayImage::from_file_buf(buf).map(|image| {
-            let factor = ((image.width() * image.height() / 160) as f32)
-                .sqrt()
-                .sqrt() as u32;
-            let factor = factor.max(2);
-
-            let image = image.into_compressed(factor);
-            let packed_image = image.to_packed();
-            let mut square_sum = 0u64;
-            for y in 0..image.height() {
-                for x in 0..image.width() {
-                    square_sum += (image.pixel(x, y) as u64).pow(2);
-                }
-            }
+        let mut pattern = Self::from_screenshot(&Screenshot::from_file_buf(buf)?);
+        pattern.checksum = checksum(buf);
+        Ok(pattern)
+    }
+
+    /// Like `from_file_buf`, with the image resized by `scale` first, such as
+    /// to match a screen of another resolution than it was cropped from.
+    pub fn from_file_buf_scaled(buf: &[u8], scale: f64) -> Result<Self> {
+        let screenshot = Screenshot::from_file_buf(buf)?;
+        let size = screenshot.size().scale(scale, scale);
+        let size = Size::new(size.width.max(1), size.height.max(1));
+        let mut pattern = if size == screenshot.size() {
+            Self::from_screenshot(&screenshot)
+        } else {
+            Self::from_screenshot(&screenshot.resize(size))
+        };
+        pattern.checksum = checksum(buf);
+        pattern.scale = scale;
+        Ok(pattern)
+    }
+
+    /// Creates a pattern from a part of a frame, such as to find an element
+    /// again once it has moved. Unlike the patterns loaded from files, the
+    /// pattern is at the resolution of the frame.
+    pub fn from_screenshot_region(screenshot: &Screenshot, rect: Rect) -> Result<Self> {
+        Self::check_size(rect.width, rect.height)?;
+        Self::try_from_screenshot(&screenshot.crop(rect)?)
+    }
+
+    /// Creates a pattern from rows of one luma byte per pixel.
+    pub fn from_luma_buf(width: u32, height: u32, buf: &[u8]) -> Result<Self> {
+        Self::check_size(width, height)?;
+        let screenshot =
+            Screenshot::from_raw(width, height, width as usize, PixelFormat::Gray, buf)?;
+        Self::try_from_screenshot(&screenshot)
+    }
+
+    /// Creates a pattern from rows of BGRA pixels, as captured.
+    pub fn from_bgra_buf(width: u32, height: u32, buf: &[u8]) -> Result<Self> {
+        Self::check_size(width, height)?;
+        let stride = width as usize * 4;
+        let screenshot = Screenshot::from_raw(width, height, stride, PixelFormat::Bgra, buf)?;
+        Self::try_from_screenshot(&screenshot)
+    }
+
+    // Fails for an empty image, before building a screenshot of it
+    fn check_size(width: u32, height: u32) -> Result<()> {
+        if width == 0 || height == 0 {
+            return Err(format!(
+                "A {}x{} image is too small for a pattern",
+                width, height
+            ));
+        }
+        Ok(())
+    }
+
+    // Like `from_screenshot`, failing for images too small to compress
+    fn try_from_screenshot(screenshot: &Screenshot) -> Result<Self> {
+        let pattern = Self::from_screenshot(screenshot);
+        if pattern.width() == 0 || pattern.height() == 0 {
+            return Err(format!(
+                "A {}x{} image is too small for a pattern",
+                screenshot.width(),
+                screenshot.height()
+            ));
+        }
+        Ok(pattern)
+    }
+
+    pub(super) fn from_screenshot(screenshot: &Screenshot) -> Self {
+        let factor = ((screenshot.width() * screenshot.height() / 160) as f32)
+            .sqrt()
+            .sqrt() as u32;
+        let factor = factor.max(2);
+
+        let image = GrayImage::from_screenshot_compressed(screenshot, factor);
+        Self::from_compressed(screenshot.size(), factor, image)
+    }
+
+    fn from_compressed(size: Size, factor: u32, image: GrayImage) -> Self {
+        let (packed_image, sum, rest_square_sums) = Self::summarize(&image);
+        Self {
+            checksum: 0,
+            size,
+            scale: 1.0,
+            hotspot: Default::default(),
+            threshold: Self::DEFAULT_THRESHOLD,
+            region: None,
+            mask: None,
+            factor,
+            image,
+            packed_image,
+            sum,
+            square_sum: rest_square_sums[0],
+            rest_square_sums,
+        }
+    }
 
-            Self {
-                factor,
-                image,
-                packed_image,
-                square_sum,
+    fn summarize(image: &GrayImage) -> (PackedGrayImage, u64, Vec<u64>) {
+        let packed_image = image.to_packed();
+        let mut sum = 0u64;
+        let mut rest_square_sums = vec![0u64; image.height() as usize + 1];
+        for y in (0..image.height()).rev() {
+            let mut row_square_sum = 0u64;
+            for x in 0..image.width() {
+                let luma = image.pixel(x, y) as u64;
+                sum += luma;
+                row_square_sum += luma * luma;
             }
-        })
+            rest_square_sums[y as usize] = rest_square_sums[y as usize + 1] + row_square_sum;
+        }
+        (packed_image, sum, rest_square_sums)
+    }
+
+    // Least similarity of a match unless set otherwise
+    const DEFAULT_THRESHOLD: f32 = 0.99;
+
+    /// Least similarity of a match, from 0 to 1.
+    #[inline]
+    pub fn threshold(&self) -> f32 {
+        self.threshold
+    }
+
+    pub fn set_threshold(&mut self, threshold: f32) {
+        self.threshold = threshold;
+    }
+
+    /// Region of the captured frames the pattern is looked for in, by the
+    /// center of its matches.
+    #[inline]
+    pub fn region(&self) -> Option<Rect> {
+        self.region
+    }
+
+    pub fn set_region(&mut self, region: Option<Rect>) {
+        self.region = region;
+    }
+
+    /// Ignores the pixels where `mask` is dark, such as the background
+    /// around an icon, in addition to the ones ignored by a previous mask.
+    /// The mask is resized to the size of the pattern if needed.
+    ///
+    /// Masked patterns are found by a slower search.
+    pub fn set_mask(&mut self, mask: &Screenshot) {
+        let compressed = if mask.size() == self.size {
+            GrayImage::from_screenshot_compressed(mask, self.factor)
+        } else {
+            GrayImage::from_screenshot_compressed(&mask.resize(self.size), self.factor)
+        };
+        let mask: Vec<bool> = match &self.mask {
+            Some(previous) => compressed
+                .as_bytes()
+                .iter()
+                .zip(previous)
+                .map(|(&luma, &kept)| kept && luma >= 128)
+                .collect(),
+            None => compressed
+                .as_bytes()
+                .iter()
+                .map(|&luma| luma >= 128)
+                .collect(),
+        };
+
+        // Ignored pixels take no part in the products of the correlation
+        let lumas = self
+            .image
+            .as_bytes()
+            .iter()
+            .zip(&mask)
+            .map(|(&luma, &kept)| if kept { luma } else { 0 })
+            .collect();
+        self.image = GrayImage::from_raw(self.image.width(), self.image.height(), lumas).unwrap();
+        let (packed_image, sum, rest_square_sums) = Self::summarize(&self.image);
+        self.packed_image = packed_image;
+        self.sum = sum;
+        self.square_sum = rest_square_sums[0];
+        self.rest_square_sums = rest_square_sums;
+        self.mask = Some(mask);
+    }
+
+    /// Tells whether each pixel of the compressed image is compared, row by
+    /// row.
+    #[inline]
+    pub(super) fn mask(&self) -> Option<&[bool]> {
+        self.mask.as_deref()
+    }
+
+    /// Checksum of the file the pattern was loaded from, or zero if it was
+    /// not loaded from a file.
+    #[inline]
+    pub fn checksum(&self) -> u64 {
+        self.checksum
+    }
+
+    /// Size of the image the pattern was made from.
+    #[inline]
+    pub fn size(&self) -> Size {
+        self.size
+    }
+
+    /// Factor the image was resized by, see `from_file_buf_scaled`.
+    #[inline]
+    pub fn scale(&self) -> f64 {
+        self.scale
+    }
+
+    #[inline]
+    pub fn hotspot(&self) -> Hotspot {
+        self.hotspot
+    }
+
+    pub fn set_hotspot(&mut self, hotspot: Hotspot) {
+        self.hotspot = hotspot;
+    }
+
+    /// Returns the area covered by a match found at `center`.
+    pub fn area(&self, center: Point) -> Rect {
+        Rect::centered_at(center, self.size)
+    }
+
+    /// Returns the position to act on for a match found at `center`: the
+    /// hotspot moved by `dx` and `dy`, given like the hotspot offset. `None`
+    /// if it's left of or above the frame.
+    pub fn target(&self, center: Point, (dx, dy): (i32, i32)) -> Option<Point> {
+        let area = Rect::from_origin_size(Point::default(), self.size);
+        let anchor = area.anchor(self.hotspot.anchor);
+        let middle = area.center();
+        let scaled = |offset: i32| (offset as f64 * self.scale).round() as i64;
+        let (x, y) = self.hotspot.offset;
+        let x = center.x as i64 + anchor.x as i64 - middle.x as i64 + scaled(x + dx);
+        let y = center.y as i64 + anchor.y as i64 - middle.y as i64 + scaled(y + dy);
+        Some(Point::new(u32::try_from(x).ok()?, u32::try_from(y).ok()?))
     }
 
     #[inline]
@@ -53,19 +317,30 @@ impl Pattern {
         self.image.height()
     }
 
+    #[inline]
+    pub fn sum(&self) -> u64 {
+        self.sum
+    }
+
     #[inline]
     pub fn square_sum(&self) -> u64 {
         self.square_sum
     }
 
+    /// Square sum of the rows from `y` to the bottom of the pattern.
+    #[inline]
+    pub fn rest_square_sum(&self, y: u32) -> u64 {
+        self.rest_square_sums[y as usize]
+    }
+
     #[inline]
     pub fn pixel(&self, x: u32, y: u32) -> u8 {
         self.image.pixel(x, y)
     }
 
     #[inline]
-    pub fn packed_pixels(&self, x: u32, y: u32) -> &u16x8 {
-        self.packed_image.pixels(x, y)
+    pub(super) fn packed(&self) -> &PackedGrayImage {
+        &self.packed_image
     }
 
     pub fn save<T>(&self, path: T) -> Result<()>
@@ -75,3 +350,357 @@ impl Pattern {
         self.image.save(path)
     }
 }
+
+// Compiled patterns are stored as, in little endian:
+//
+// - the magic bytes and the format version
+// - the checksum of the source file, the scale it was loaded with and the
+//   size of the source image
+// - the hotspot, as the index of its anchor and its offset
+// - the threshold, and the region as a presence byte and its rectangle
+// - the factor and size of the compressed image
+// - the sum and square sum of the compressed image, to detect corruption
+// - the lumas of the compressed image, row by row
+// - the mask as a presence byte and one byte per compressed pixel
+//
+// The packed image is rebuilt on load, as its layout depends on the vector
+// width of the target.
+const COMPILED_MAGIC: &[u8; 4] = b"APAT";
+const COMPILED_VERSION: u16 = 2;
+
+const ANCHORS: [Anchor; 9] = [
+    Anchor::TopLeft,
+    Anchor::Top,
+    Anchor::TopRight,
+    Anchor::Left,
+    Anchor::Center,
+    Anchor::Right,
+    Anchor::BottomLeft,
+    Anchor::Bottom,
+    Anchor::BottomRight,
+];
+
+/// FNV-1a hash of a file, telling whether a compiled pattern is stale.
+fn checksum(buf: &[u8]) -> u64 {
+    buf.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+fn take<'a>(buf: &mut &'a [u8], len: usize) -> Result<&'a [u8]> {
+    if buf.len() < len {
+        return Err("Compiled pattern is truncated".to_string());
+    }
+    let (head, tail) = buf.split_at(len);
+    *buf = tail;
+    Ok(head)
+}
+
+fn take_array<const N: usize>(buf: &mut &[u8]) -> Result<[u8; N]> {
+    Ok(take(buf, N)?.try_into().unwrap())
+}
+
+impl Pattern {
+    /// Serializes the pattern into the compiled format, which loads much
+    /// faster than decoding and compressing an image.
+    pub fn to_compiled(&self) -> Vec<u8> {
+        let anchor = ANCHORS
+            .iter()
+            .position(|&anchor| anchor == self.hotspot.anchor)
+            .unwrap() as u8;
+        let (dx, dy) = self.hotspot.offset;
+
+        let mut buf = Vec::with_capacity(96 + 2 * self.image.as_bytes().len());
+        buf.extend_from_slice(COMPILED_MAGIC);
+        buf.extend_from_slice(&COMPILED_VERSION.to_le_bytes());
+        buf.extend_from_slice(&self.checksum.to_le_bytes());
+        buf.extend_from_slice(&self.scale.to_le_bytes());
+        buf.extend_from_slice(&self.size.width.to_le_bytes());
+        buf.extend_from_slice(&self.size.height.to_le_bytes());
+        buf.push(anchor);
+        buf.extend_from_slice(&dx.to_le_bytes());
+        buf.extend_from_slice(&dy.to_le_bytes());
+        buf.extend_from_slice(&self.threshold.to_le_bytes());
+        buf.push(self.region.is_some() as u8);
+        let region = self.region.unwrap_or_default();
+        for value in [region.x, region.y, region.width, region.height] {
+            buf.extend_from_slice(&value.to_le_bytes());
+        }
+        buf.extend_from_slice(&self.factor.to_le_bytes());
+        buf.extend_from_slice(&self.image.width().to_le_bytes());
+        buf.extend_from_slice(&self.image.height().to_le_bytes());
+        buf.extend_from_slice(&self.sum.to_le_bytes());
+        buf.extend_from_slice(&self.square_sum.to_le_bytes());
+        buf.extend_from_slice(self.image.as_bytes());
+        buf.push(self.mask.is_some() as u8);
+        if let Some(mask) = &self.mask {
+            buf.extend(mask.iter().map(|&kept| kept as u8));
+        }
+        buf
+    }
+
+    /// Deserializes a pattern written by `to_compiled`.
+    pub fn from_compiled(mut buf: &[u8]) -> Result<Self> {
+        let buf = &mut buf;
+        if take(buf, 4)? != COMPILED_MAGIC {
+            return Err("Not a compiled pattern".to_string());
+        }
+        let version = u16::from_le_bytes(take_array(buf)?);
+        if version != COMPILED_VERSION {
+            return Err(format!("Unsupported compiled pattern version {}", version));
+        }
+        let checksum = u64::from_le_bytes(take_array(buf)?);
+        let scale = f64::from_le_bytes(take_array(buf)?);
+        let width = u32::from_le_bytes(take_array(buf)?);
+        let height = u32::from_le_bytes(take_array(buf)?);
+        let anchor = *ANCHORS
+            .get(take(buf, 1)?[0] as usize)
+            .ok_or_else(|| "Invalid hotspot anchor".to_string())?;
+        let dx = i32::from_le_bytes(take_array(buf)?);
+        let dy = i32::from_le_bytes(take_array(buf)?);
+        let threshold = f32::from_le_bytes(take_array(buf)?);
+        let has_region = take(buf, 1)?[0] != 0;
+        let mut region = [0u32; 4];
+        for value in region.iter_mut() {
+            *value = u32::from_le_bytes(take_array(buf)?);
+        }
+        let factor = u32::from_le_bytes(take_array(buf)?);
+        let image_width = u32::from_le_bytes(take_array(buf)?);
+        let image_height = u32::from_le_bytes(take_array(buf)?);
+        if factor == 0 || (image_width, image_height) != (width / factor, height / factor) {
+            return Err("Compiled pattern has an invalid size".to_string());
+        }
+        let sum = u64::from_le_bytes(take_array(buf)?);
+        let square_sum = u64::from_le_bytes(take_array(buf)?);
+        let len = image_width as usize * image_height as usize;
+        let lumas = take(buf, len)?.to_vec();
+        let mask = match take(buf, 1)?[0] {
+            0 => None,
+            _ => Some(take(buf, len)?.iter().map(|&kept| kept != 0).collect()),
+        };
+
+        let image = GrayImage::from_raw(image_width, image_height, lumas)?;
+        let mut pattern = Self::from_compressed(Size::new(width, height), factor, image);
+        if (pattern.sum, pattern.square_sum) != (sum, square_sum) {
+            return Err("Compiled pattern is corrupted".to_string());
+        }
+        pattern.checksum = checksum;
+        pattern.scale = scale;
+        pattern.hotspot = Hotspot::new(anchor, dx, dy);
+        pattern.threshold = threshold;
+        if has_region {
+            let [x, y, width, height] = region;
+            pattern.region = Some(Rect::new(x, y, width, height));
+        }
+        pattern.mask = mask;
+        Ok(pattern)
+    }
+
+    pub fn save_compiled<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        fs::write(path, self.to_compiled()).map_err(|err| err.to_string())
+    }
+
+    pub fn load_compiled<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let buf = fs::read(path).map_err(|err| err.to_string())?;
+        Self::from_compiled(&buf)
+    }
+
+    /// Like `from_file_buf_scaled`, loading the pattern compiled at `cache`
+    /// if it was compiled from the same file and scale, and compiling it
+    /// there otherwise.
+    pub fn from_file_buf_cached<P: AsRef<Path>>(buf: &[u8], scale: f64, cache: P) -> Result<Self> {
+        if let Ok(pattern) = Self::load_compiled(&cache) {
+            if pattern.checksum == checksum(buf) && pattern.scale == scale {
+                return Ok(pattern);
+            }
+        }
+        let pattern = Self::from_file_buf_scaled(buf, scale)?;
+        pattern.save_compiled(cache)?;
+        Ok(pattern)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{DynamicImage, ImageOutputFormat, Luma};
+
+    use super::super::{Direction, Finder};
+    use super::*;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn target_hotspot() {
+        let buf = vec![100u8; 40 * 20];
+        let screenshot = Screenshot::from_raw(40, 20, 40, PixelFormat::Gray, &buf).unwrap();
+        let mut pattern = Pattern::from_screenshot(&screenshot);
+        let center = Point::new(520, 310);
+        assert_eq!(pattern.target(center, (0, 0)), Some(center));
+        assert_eq!(pattern.target(center, (200, 0)), Some(Point::new(720, 310)));
+
+        pattern.set_hotspot(Hotspot::at(0, 0));
+        assert_eq!(pattern.target(center, (0, 0)), Some(Point::new(500, 300)));
+        pattern.set_hotspot(Hotspot::new(Anchor::Right, 5, 0));
+        assert_eq!(pattern.target(center, (0, -1)), Some(Point::new(544, 309)));
+
+        // Offsets are given at the size of the source image
+        pattern.scale = 2.0;
+        assert_eq!(pattern.target(center, (0, 0)), Some(Point::new(549, 310)));
+
+        // Targets left of the frame aren't moved onto its edge
+        assert_eq!(pattern.target(center, (-300, 0)), None);
+    }
+
+    #[test]
+    fn compiled_round_trip() {
+        let png = |shift: u32| {
+            let image = image::GrayImage::from_fn(48, 32, |x, y| {
+                Luma([((x * 7 + y * 3 + shift) % 256) as u8])
+            });
+            let mut buf = vec![];
+            DynamicImage::ImageLuma8(image)
+                .write_to(&mut buf, ImageOutputFormat::Png)
+                .unwrap();
+            buf
+        };
+        let buf = png(0);
+        let mut pattern = Pattern::from_file_buf(&buf).unwrap();
+        pattern.set_hotspot(Hotspot::new(Anchor::Left, -3, 2));
+        pattern.set_threshold(0.95);
+        pattern.set_region(Some(Rect::new(0, 0, 640, 80)));
+
+        let loaded = Pattern::from_compiled(&pattern.to_compiled()).unwrap();
+        assert_eq!(loaded.checksum(), pattern.checksum());
+        assert_eq!(
+            (loaded.size(), loaded.factor()),
+            (pattern.size(), pattern.factor())
+        );
+        assert_eq!(loaded.hotspot(), pattern.hotspot());
+        assert_eq!(
+            (loaded.threshold(), loaded.region()),
+            (pattern.threshold(), pattern.region())
+        );
+        assert_eq!(loaded.square_sum(), pattern.square_sum());
+        assert!(Pattern::from_compiled(&pattern.to_compiled()[..40]).is_err());
+
+        // The factor follows the magic, version, checksum, scale, size,
+        // hotspot, threshold and region, then the compressed size
+        let factor_at = 4 + 2 + 8 + 8 + 8 + 1 + 8 + 4 + 1 + 16;
+        let mut zero_factor = pattern.to_compiled();
+        assert_eq!(
+            zero_factor[factor_at..factor_at + 4],
+            pattern.factor().to_le_bytes()
+        );
+        zero_factor[factor_at..factor_at + 4].copy_from_slice(&0u32.to_le_bytes());
+        assert!(Pattern::from_compiled(&zero_factor).is_err());
+        let mut wrong_width = pattern.to_compiled();
+        let width_at = factor_at + 4;
+        wrong_width[width_at..width_at + 4].copy_from_slice(&1u32.to_le_bytes());
+        assert!(Pattern::from_compiled(&wrong_width).is_err());
+
+        // The cache is rewritten once the source changes
+        let dir = TempDir::new("pattern");
+        let cache = dir.join("pattern.apat");
+        let first = Pattern::from_file_buf_cached(&buf, 1.0, &cache).unwrap();
+        assert_eq!(
+            Pattern::load_compiled(&cache).unwrap().checksum(),
+            first.checksum()
+        );
+        let changed = png(1);
+        let second = Pattern::from_file_buf_cached(&changed, 1.0, &cache).unwrap();
+        assert_ne!(second.checksum(), first.checksum());
+        assert_eq!(
+            Pattern::load_compiled(&cache).unwrap().checksum(),
+            second.checksum()
+        );
+    }
+
+    #[test]
+    fn masked_pattern_ignores_background() {
+        let icon = |x: u32, y: u32| ((x * 11 + y * 7) % 200 + 30) as u8;
+        let (width, height) = (120u32, 60u32);
+        let mut screen = vec![0u8; (width * height) as usize];
+        for y in 0..20 {
+            for x in 0..40 {
+                // The right half is a background differing from the pattern's
+                let luma = if x < 20 {
+                    icon(x, y)
+                } else {
+                    ((x * y) % 97) as u8
+                };
+                screen[((20 + y) * width + 50 + x) as usize] = luma;
+            }
+        }
+        let screen =
+            Screenshot::from_raw(width, height, width as usize, PixelFormat::Gray, &screen)
+                .unwrap();
+        let lumas: Vec<u8> = (0..20)
+            .flat_map(|y| (0..40).map(move |x| icon(x, y)))
+            .collect();
+        let image = Screenshot::from_raw(40, 20, 40, PixelFormat::Gray, &lumas).unwrap();
+        let mut pattern = Pattern::from_screenshot(&image);
+        assert_eq!(Finder::new(&screen).find(&pattern, Direction::Up), None);
+
+        let mask: Vec<u8> = (0..20)
+            .flat_map(|_| (0..40).map(|x| if x < 20 { 255 } else { 0 }))
+            .collect();
+        pattern.set_mask(&Screenshot::from_raw(40, 20, 40, PixelFormat::Gray, &mask).unwrap());
+        let pos = Finder::new(&screen).find(&pattern, Direction::Up).unwrap();
+        assert!(Rect::new(65, 25, 10, 10).contains(pos), "{:?}", pos);
+
+        pattern.set_region(Some(Rect::new(0, 0, 60, 60)));
+        assert_eq!(Finder::new(&screen).find(&pattern, Direction::Up), None);
+    }
+
+    #[test]
+    fn pattern_from_frame_region() {
+        let (width, height) = (200u32, 100u32);
+        let lumas: Vec<u8> = (0..width * height)
+            .map(|i| ((i % width) * (i % width) * 7 + (i / width) * 13 + i % 31) as u8)
+            .collect();
+        let screenshot =
+            Screenshot::from_raw(width, height, width as usize, PixelFormat::Gray, &lumas).unwrap();
+
+        let rect = Rect::new(60, 40, 40, 20);
+        let pattern = Pattern::from_screenshot_region(&screenshot, rect).unwrap();
+        assert_eq!(pattern.size(), rect.size());
+        let finder = Finder::new(&screenshot);
+        assert_eq!(
+            finder.find(&pattern, Direction::Down),
+            Some(Point::new(80, 50))
+        );
+
+        let item: Vec<u8> = (40..60)
+            .flat_map(|y| &lumas[(y * width + 60) as usize..(y * width + 100) as usize])
+            .copied()
+            .collect();
+        let pattern = Pattern::from_luma_buf(40, 20, &item).unwrap();
+        assert_eq!(
+            finder.find(&pattern, Direction::Down),
+            Some(Point::new(80, 50))
+        );
+        let bgra = screenshot.crop(rect).unwrap();
+        let pattern = Pattern::from_bgra_buf(40, 20, bgra.bgra_buf()).unwrap();
+        assert_eq!(
+            finder.find(&pattern, Direction::Down),
+            Some(Point::new(80, 50))
+        );
+
+        assert!(Pattern::from_screenshot_region(&screenshot, Rect::new(180, 0, 40, 20)).is_err());
+        assert!(Pattern::from_screenshot_region(&screenshot, Rect::new(0, 0, 1, 1)).is_err());
+        assert!(Pattern::from_luma_buf(40, 20, &item[1..]).is_err());
+
+        // Empty images and regions past the end of the coordinates
+        assert!(Pattern::from_screenshot_region(&screenshot, Rect::new(60, 40, 0, 20)).is_err());
+        assert!(
+            Pattern::from_screenshot_region(&screenshot, Rect::new(u32::MAX - 5, 0, 10, 10))
+                .is_err()
+        );
+        assert!(
+            Pattern::from_screenshot_region(&screenshot, Rect::new(0, u32::MAX - 5, 10, 10))
+                .is_err()
+        );
+        assert!(Pattern::from_luma_buf(0, 10, &[]).is_err());
+        assert!(Pattern::from_luma_buf(10, 0, &[]).is_err());
+        assert!(Pattern::from_bgra_buf(0, 10, &[]).is_err());
+    }
+}
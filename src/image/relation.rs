@@ -0,0 +1,157 @@
+use crate::geometry::{Point, Rect};
+
+use super::{Direction, Finder, Pattern, PatternLibrary, PatternRef};
+
+/// How a match stands relative to the match of another pattern.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Relation {
+    /// Overlaps the other match vertically, as in the same row of a table.
+    SameRow,
+    /// Overlaps the other match horizontally.
+    SameColumn,
+    /// Lies in this direction of the other match, by their centers.
+    Toward(Direction),
+    /// Has its center at most this far from the other center.
+    Within(f64),
+}
+
+impl Relation {
+    /// Tells whether a match covering `area` stands in this relation to one
+    /// covering `anchor`.
+    pub fn holds(&self, anchor: Rect, area: Rect) -> bool {
+        match self {
+            Relation::SameRow => area.y < anchor.bottom() && anchor.y < area.bottom(),
+            Relation::SameColumn => area.x < anchor.right() && anchor.x < area.right(),
+            Relation::Toward(dir) => dir.meet(anchor.center(), area.center()),
+            Relation::Within(distance) => anchor.center().distance(area.center()) <= *distance,
+        }
+    }
+}
+
+/// Finds a pattern among the matches standing in some relations to the match
+/// of an anchor pattern, such as the button in the same row as a label.
+pub struct RelativeQuery<'a> {
+    anchor: PatternRef<'a>,
+    anchor_dir: Direction,
+    relations: Vec<Relation>,
+}
+
+impl<'a> RelativeQuery<'a> {
+    /// The anchor is the match of `anchor` found towards `anchor_dir`.
+    pub fn new<P: Into<PatternRef<'a>>>(
+        anchor: P,
+        anchor_dir: Direction,
+        relations: Vec<Relation>,
+    ) -> Self {
+        Self {
+            anchor: anchor.into(),
+            anchor_dir,
+            relations,
+        }
+    }
+
+    pub fn anchor(&self) -> &PatternRef<'a> {
+        &self.anchor
+    }
+
+    pub fn relations(&self) -> &[Relation] {
+        &self.relations
+    }
+
+    /// Finds `pattern` towards `dir` among the matches standing in every
+    /// relation, returning the centers of the anchor and of the match. A
+    /// named anchor is taken from `library`.
+    pub fn find(
+        &self,
+        finder: &Finder,
+        library: Option<&PatternLibrary>,
+        pattern: &Pattern,
+        dir: Direction,
+    ) -> Option<(Point, Point)> {
+        let anchor = self.anchor.resolve(library)?;
+        self.find_from(finder, &anchor, pattern, dir)
+    }
+
+    // Like `find`, with the anchor resolved already
+    pub(crate) fn find_from(
+        &self,
+        finder: &Finder,
+        anchor: &Pattern,
+        pattern: &Pattern,
+        dir: Direction,
+    ) -> Option<(Point, Point)> {
+        let center = finder.find(anchor, self.anchor_dir)?;
+        let anchor_area = anchor.area(center);
+        let pos = finder.find_where(pattern, dir, |center| {
+            let area = pattern.area(center);
+            self.relations
+                .iter()
+                .all(|relation| relation.holds(anchor_area, area))
+        })?;
+        Some((center, pos))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{PixelFormat, Screenshot};
+    use super::*;
+
+    // Rows of a table, each with a label and a button, where the labels
+    // differ by the stripes they have
+    fn table() -> (Screenshot, Vec<u8>, Vec<u8>) {
+        let (width, height) = (200u32, 120u32);
+        let mut buf = vec![0u8; (width * height) as usize];
+        let mut draw = |x0: u32, y0: u32, luma: &dyn Fn(u32, u32) -> u8| {
+            for y in 0..20 {
+                for x in 0..40 {
+                    buf[((y0 + y) * width + x0 + x) as usize] = luma(x, y);
+                }
+            }
+        };
+        let label = |stripe: u32| move |x: u32, y: u32| ((x / stripe + y / 5) % 2 * 200 + 30) as u8;
+        let button = |x: u32, y: u32| ((x * 7 + y * 13) % 251) as u8;
+        for (row, stripe) in [(0, 4), (1, 8), (2, 10)] {
+            draw(10, 10 + row * 40, &label(stripe));
+            draw(140, 10 + row * 40, &button);
+        }
+
+        let crop = |luma: &dyn Fn(u32, u32) -> u8| {
+            (0..20)
+                .flat_map(|y| (0..40).map(move |x| (x, y)))
+                .map(|(x, y)| luma(x, y))
+                .collect::<Vec<_>>()
+        };
+        let screenshot =
+            Screenshot::from_raw(width, height, width as usize, PixelFormat::Gray, &buf).unwrap();
+        (screenshot, crop(&label(8)), crop(&button))
+    }
+
+    #[test]
+    fn find_in_same_row() {
+        let (screenshot, label, button) = table();
+        let pattern = |buf: &[u8]| {
+            Pattern::from_screenshot(
+                &Screenshot::from_raw(40, 20, 40, PixelFormat::Gray, buf).unwrap(),
+            )
+        };
+        let (label, button) = (pattern(&label), pattern(&button));
+        let finder = Finder::new(&screenshot);
+
+        let query = RelativeQuery::new(
+            &label,
+            Direction::Up,
+            vec![Relation::SameRow, Relation::Toward(Direction::Right)],
+        );
+        let (anchor, pos) = query.find(&finder, None, &button, Direction::Up).unwrap();
+        assert!(Rect::new(10, 50, 40, 20).contains(anchor));
+        assert!(Rect::new(140, 50, 40, 20).contains(pos));
+
+        let query = RelativeQuery::new(&label, Direction::Up, vec![Relation::Within(50.0)]);
+        assert_eq!(query.find(&finder, None, &button, Direction::Up), None);
+
+        // A named anchor is only found in a library
+        let query = RelativeQuery::new("label", Direction::Up, vec![Relation::SameRow]);
+        assert_eq!(query.find(&finder, None, &button, Direction::Up), None);
+    }
+}
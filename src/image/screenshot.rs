@@ -1,8 +1,12 @@
 use std::path::Path;
 
+use image::imageops::{self, FilterType};
 use image::{GenericImageView, RgbaImage};
 
 use crate::error::Result;
+use crate::geometry::{Rect, Size};
+
+use super::Diff;
 
 #[derive(PartialEq)]
 pub struct Pixel<'a> {
@@ -31,17 +35,42 @@ impl<'a> Pixel<'a> {
     }
 
     pub fn luma(&self) -> u8 {
-        const BGR_LUMA_FACTOR: [u32; 3] = [722, 7152, 2126];
-        const SCALE: u32 = 10000;
-        let mut luma = 0u32;
-        for (v, factor) in self.bgra.iter().zip(BGR_LUMA_FACTOR.iter()) {
-            luma += *v as u32 * factor;
+        bgr_to_luma(self.b(), self.g(), self.r())
+    }
+}
+
+#[inline]
+pub(super) fn bgr_to_luma(b: u8, g: u8, r: u8) -> u8 {
+    const BGR_LUMA_FACTOR: [u32; 3] = [722, 7152, 2126];
+    const SCALE: u32 = 10000;
+    let luma = b as u32 * BGR_LUMA_FACTOR[0]
+        + g as u32 * BGR_LUMA_FACTOR[1]
+        + r as u32 * BGR_LUMA_FACTOR[2];
+    (luma / SCALE) as u8
+}
+
+/// Layout of a pixel in a raw image buffer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PixelFormat {
+    Bgra,
+    /// BGRA with an unused fourth byte, read as opaque.
+    Bgrx,
+    Rgba,
+    Rgb,
+    Gray,
+}
+
+impl PixelFormat {
+    pub fn bytes_per_pixel(&self) -> usize {
+        match self {
+            PixelFormat::Bgra | PixelFormat::Bgrx | PixelFormat::Rgba => 4,
+            PixelFormat::Rgb => 3,
+            PixelFormat::Gray => 1,
         }
-        (luma / SCALE) as u8
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct Screenshot {
     width: u32,
     height: u32,
@@ -60,6 +89,47 @@ impl Screenshot {
         })
     }
 
+    /// Creates a screenshot from rows of `format` pixels that start every
+    /// `stride` bytes, as delivered by capture APIs padding their rows.
+    pub fn from_raw(
+        width: u32,
+        height: u32,
+        stride: usize,
+        format: PixelFormat,
+        buf: &[u8],
+    ) -> Result<Self> {
+        let mut bgra_buf = Vec::new();
+        Self::repack(width, height, stride, format, buf, &mut bgra_buf)?;
+        Ok(Screenshot {
+            width,
+            height,
+            bgra_buf,
+        })
+    }
+
+    /// Like `from_raw`, but overwrites this screenshot and reuses its buffer.
+    pub fn fill_from_raw(
+        &mut self,
+        width: u32,
+        height: u32,
+        stride: usize,
+        format: PixelFormat,
+        buf: &[u8],
+    ) -> Result<()> {
+        Self::repack(width, height, stride, format, buf, &mut self.bgra_buf)?;
+        self.width = width;
+        self.height = height;
+        Ok(())
+    }
+
+    /// Copies another screenshot, reusing this buffer.
+    pub fn copy_from(&mut self, other: &Screenshot) {
+        self.width = other.width;
+        self.height = other.height;
+        self.bgra_buf.clear();
+        self.bgra_buf.extend_from_slice(&other.bgra_buf);
+    }
+
     pub fn from_file_buf(buf: &[u8]) -> Result<Self> {
         match image::load_from_memory(buf) {
             Ok(dyn_img) => Ok(Screenshot::from_bgra_buf(
@@ -80,6 +150,55 @@ impl Screenshot {
         self.height
     }
 
+    pub fn size(&self) -> Size {
+        Size::new(self.width, self.height)
+    }
+
+    pub fn bgra_buf(&self) -> &[u8] {
+        &self.bgra_buf
+    }
+
+    /// Compares with another screenshot, only within `region` if given.
+    pub fn diff(&self, other: &Screenshot, region: Option<Rect>) -> Diff {
+        Diff::between(self, other, region)
+    }
+
+    /// Returns a copy resized to `size` with a triangle filter.
+    pub fn resize(&self, size: Size) -> Screenshot {
+        let Size { width, height } = size;
+        // Channels are filtered alike, so the BGRA buffer passes for RGBA
+        let image = RgbaImage::from_raw(self.width, self.height, self.bgra_buf.clone()).unwrap();
+        let resized = imageops::resize(&image, width, height, FilterType::Triangle);
+        Screenshot {
+            width,
+            height,
+            bgra_buf: resized.into_raw(),
+        }
+    }
+
+    /// Returns a copy of `rect`, which must lie within the screenshot.
+    pub fn crop(&self, rect: Rect) -> Result<Screenshot> {
+        let fits = |start: u32, len: u32, limit: u32| match start.checked_add(len) {
+            Some(end) => end <= limit,
+            None => false,
+        };
+        if !fits(rect.x, rect.width, self.width) || !fits(rect.y, rect.height, self.height) {
+            return Err(format!(
+                "{:?} is outside of a {}x{} screenshot",
+                rect, self.width, self.height
+            ));
+        }
+        let stride = self.width as usize * 4;
+        let head = rect.y as usize * stride + rect.x as usize * 4;
+        Self::from_raw(
+            rect.width,
+            rect.height,
+            stride,
+            PixelFormat::Bgra,
+            &self.bgra_buf[head..],
+        )
+    }
+
     pub fn pixel(&self, x: u32, y: u32) -> Pixel {
         let head = (y * self.width + x) * 4;
         let head = head as usize;
@@ -96,9 +215,123 @@ impl Screenshot {
         img.save(path).map_err(|_| "Unknown error".to_string())
     }
 
+    fn repack(
+        width: u32,
+        height: u32,
+        stride: usize,
+        format: PixelFormat,
+        buf: &[u8],
+        bgra_buf: &mut Vec<u8>,
+    ) -> Result<()> {
+        let row_len = width as usize * format.bytes_per_pixel();
+        if width == 0 || stride == 0 {
+            return Err(format!("Invalid width {} or stride {}", width, stride));
+        }
+        if stride < row_len {
+            return Err(format!(
+                "Stride {} is less than row length {}",
+                stride, row_len
+            ));
+        }
+        if height > 0 && buf.len() < stride * (height as usize - 1) + row_len {
+            return Err(format!(
+                "Buffer of {} bytes is too small for {} rows with stride {}",
+                buf.len(),
+                height,
+                stride
+            ));
+        }
+
+        bgra_buf.clear();
+        bgra_buf.reserve(width as usize * height as usize * 4);
+        if format == PixelFormat::Bgra && stride == row_len {
+            bgra_buf.extend_from_slice(&buf[..row_len * height as usize]);
+            return Ok(());
+        }
+        for row in buf.chunks(stride).take(height as usize) {
+            let row = &row[..row_len];
+            match format {
+                PixelFormat::Bgra => bgra_buf.extend_from_slice(row),
+                PixelFormat::Bgrx => {
+                    for bgrx in row.chunks_exact(4) {
+                        bgra_buf.extend_from_slice(&[bgrx[0], bgrx[1], bgrx[2], u8::MAX]);
+                    }
+                }
+                PixelFormat::Rgba => {
+                    for rgba in row.chunks_exact(4) {
+                        bgra_buf.extend_from_slice(&[rgba[2], rgba[1], rgba[0], rgba[3]]);
+                    }
+                }
+                PixelFormat::Rgb => {
+                    for rgb in row.chunks_exact(3) {
+                        bgra_buf.extend_from_slice(&[rgb[2], rgb[1], rgb[0], u8::MAX]);
+                    }
+                }
+                PixelFormat::Gray => {
+                    for &luma in row {
+                        bgra_buf.extend_from_slice(&[luma, luma, luma, u8::MAX]);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
     fn swap_chanel_r_and_b(buf: &mut Vec<u8>) {
         for i in (0..buf.len()).step_by(4) {
             buf.swap(i, i + 2);
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repack_padded_rows() {
+        let (width, height, stride) = (3, 2, 16);
+        let mut rgb_buf = vec![0u8; stride * height as usize];
+        for y in 0..height as usize {
+            for x in 0..width as usize {
+                let head = y * stride + x * 3;
+                rgb_buf[head..head + 3].copy_from_slice(&[x as u8, y as u8, 7]);
+            }
+        }
+
+        let screenshot =
+            Screenshot::from_raw(width, height, stride, PixelFormat::Rgb, &rgb_buf).unwrap();
+        for y in 0..height {
+            for x in 0..width {
+                let pixel = screenshot.pixel(x, y);
+                assert_eq!(
+                    (pixel.r(), pixel.g(), pixel.b(), pixel.a()),
+                    (x as u8, y as u8, 7, u8::MAX)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn reject_short_stride() {
+        let buf = vec![0u8; 64];
+        assert!(Screenshot::from_raw(4, 4, 8, PixelFormat::Bgra, &buf).is_err());
+        assert!(Screenshot::from_raw(4, 5, 16, PixelFormat::Bgra, &buf).is_err());
+        assert!(Screenshot::from_raw(4, 4, 16, PixelFormat::Bgra, &buf).is_ok());
+        assert!(Screenshot::from_raw(0, 4, 0, PixelFormat::Gray, &[]).is_err());
+        assert!(Screenshot::from_raw(0, 4, 16, PixelFormat::Gray, &buf).is_err());
+    }
+
+    #[test]
+    fn fill_reuses_buffer() {
+        let mut screenshot = Screenshot::from_raw(8, 8, 32, PixelFormat::Bgra, &[0; 256]).unwrap();
+        let buf_ptr = screenshot.bgra_buf().as_ptr();
+
+        screenshot
+            .fill_from_raw(4, 4, 4, PixelFormat::Gray, &[9; 16])
+            .unwrap();
+        assert_eq!((screenshot.width(), screenshot.height()), (4, 4));
+        assert_eq!(screenshot.bgra_buf().as_ptr(), buf_ptr);
+        assert_eq!(screenshot.pixel(3, 3).luma(), 9);
+    }
+}
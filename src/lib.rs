@@ -1,9 +1,12 @@
-#![feature(portable_simd)]
+#![cfg_attr(feature = "simd", feature(portable_simd))]
 
 pub mod context;
 pub mod fsm;
+pub mod geometry;
 pub mod image;
 
 mod error;
+#[cfg(test)]
+mod test_util;
 
 pub use error::*;
@@ -0,0 +1,127 @@
+//! Needs an X server with the XTEST extension and 24 bits depth, run with
+//! `xvfb-run -s "-screen 0 1280x720x24" cargo test --test x11 -- --ignored`.
+#![cfg(target_os = "linux")]
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+use automation::context::{Context, MouseButton};
+use automation::geometry::{Rect, Size};
+use automation::image::{Direction, Finder, Pattern};
+use image::{DynamicImage, GrayImage, ImageOutputFormat, Luma};
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::{
+    ConnectionExt as _, CreateGCAux, CreateWindowAux, EventMask, ImageFormat, WindowClass,
+};
+use x11rb::protocol::Event;
+use x11rb::COPY_DEPTH_FROM_PARENT;
+
+const X: i16 = 200;
+const Y: i16 = 150;
+const WIDTH: u16 = 64;
+const HEIGHT: u16 = 48;
+
+fn texture(x: u32, y: u32) -> u8 {
+    ((x * 37 + y * 91 + (x * y) % 17 * 13) % 256) as u8
+}
+
+#[test]
+#[ignore = "needs an X server"]
+fn find_and_click_pattern_on_screen() {
+    let (conn, screen) = x11rb::connect(None).unwrap();
+    let screen = &conn.setup().roots[screen];
+
+    // Show the texture in a window placed without a window manager
+    let window = conn.generate_id().unwrap();
+    conn.create_window(
+        COPY_DEPTH_FROM_PARENT,
+        window,
+        screen.root,
+        X,
+        Y,
+        WIDTH,
+        HEIGHT,
+        0,
+        WindowClass::INPUT_OUTPUT,
+        0,
+        &CreateWindowAux::new()
+            .override_redirect(1)
+            .background_pixel(screen.black_pixel)
+            .event_mask(EventMask::EXPOSURE | EventMask::BUTTON_PRESS),
+    )
+    .unwrap();
+    conn.map_window(window).unwrap();
+    conn.flush().unwrap();
+    while !matches!(conn.wait_for_event().unwrap(), Event::Expose(_)) {}
+
+    let gc = conn.generate_id().unwrap();
+    conn.create_gc(gc, window, &CreateGCAux::new()).unwrap();
+    let mut data = vec![];
+    for y in 0..HEIGHT as u32 {
+        for x in 0..WIDTH as u32 {
+            let luma = texture(x, y);
+            data.extend_from_slice(&[luma, luma, luma, 0]);
+        }
+    }
+    conn.put_image(
+        ImageFormat::Z_PIXMAP,
+        window,
+        gc,
+        WIDTH,
+        HEIGHT,
+        0,
+        0,
+        0,
+        screen.root_depth,
+        &data,
+    )
+    .unwrap();
+    conn.get_input_focus().unwrap().reply().unwrap();
+
+    let mut buf = vec![];
+    DynamicImage::ImageLuma8(GrayImage::from_fn(WIDTH as u32, HEIGHT as u32, |x, y| {
+        Luma([texture(x, y)])
+    }))
+    .write_to(&mut buf, ImageOutputFormat::Png)
+    .unwrap();
+    let pattern = Pattern::from_file_buf(&buf).unwrap();
+
+    let mut ctx = Context::new();
+    assert_eq!(
+        ctx.capturer_mut().screen_size(),
+        Size::new(
+            screen.width_in_pixels as u32,
+            screen.height_in_pixels as u32
+        )
+    );
+    let pos = Finder::new(ctx.frame())
+        .find(&pattern, Direction::Up)
+        .expect("Pattern not found on screen");
+    let center = Rect::new(X as u32, Y as u32, WIDTH as u32, HEIGHT as u32).center();
+    assert!(pos.x.abs_diff(center.x) <= pattern.factor());
+    assert!(pos.y.abs_diff(center.y) <= pattern.factor());
+
+    ctx.simulator_mut().mouse_move_to(pos.into());
+    ctx.simulator_mut().mouse_click(MouseButton::Left);
+
+    let start = Instant::now();
+    let press = loop {
+        match conn.poll_for_event().unwrap() {
+            Some(Event::ButtonPress(press)) => break press,
+            Some(_) => {}
+            None if start.elapsed() < Duration::from_secs(5) => {
+                thread::sleep(Duration::from_millis(10))
+            }
+            None => panic!("Click not received"),
+        }
+    };
+    assert_eq!(press.event, window);
+    assert_eq!(press.detail, 1);
+    assert_eq!(
+        (
+            press.event_x as i32 + X as i32,
+            press.event_y as i32 + Y as i32
+        ),
+        (pos.x as i32, pos.y as i32)
+    );
+}